@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+/// the file that says a build finished (so a half-done compile doesn't get reused)
+const DONE_MARKER: &str = ".done";
+
+/// compiled programs that stick around between runs,
+/// each one gets a directory named after the hash of what went into building it
+#[derive(Debug, Clone)]
+pub(crate) struct Cache { dir: PathBuf }
+
+/// where a single program gets built
+#[derive(Debug, Clone)]
+pub(crate) struct Build { pub(crate) dir: PathBuf }
+
+impl Cache {
+    /// the cache in the user's cache directory (e.g. `~/.cache/aargh/builds`)
+    pub(crate) fn open() -> Option<Cache> {
+        Some(Cache { dir: dirs::cache_dir()?.join("aargh").join("builds") })
+    }
+
+    pub(crate) fn clear(&self) -> std::io::Result<()> {
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir)?;
+        }
+        Ok(())
+    }
+
+    /// gets the build for some code given everything that affects how it compiles
+    /// (the source itself, the compiler & the flags)
+    pub(crate) fn build(&self, parts: &[&[u8]]) -> std::io::Result<Build> {
+        let mut hasher = Sha256::new();
+        for p in parts {
+            // the lengths keep ["ab", "c"] and ["a", "bc"] from hashing the same
+            hasher.update((p.len() as u64).to_le_bytes());
+            hasher.update(p);
+        }
+        let key: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+
+        let dir = self.dir.join(key);
+        std::fs::create_dir_all(&dir)?;
+        Ok(Build { dir })
+    }
+}
+
+impl Build {
+    pub(crate) fn is_done(&self) -> bool {
+        self.dir.join(DONE_MARKER).is_file()
+    }
+
+    pub(crate) fn finish(&self) -> std::io::Result<()> {
+        std::fs::write(self.dir.join(DONE_MARKER), "")
+    }
+}
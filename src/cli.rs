@@ -1,9 +1,18 @@
 use structopt::StructOpt;
+use std::ffi::OsString;
 use std::path::PathBuf;
+use std::str::FromStr;
+
+use strum::IntoEnumIterator;
+use strum_macros::{EnumIter, IntoStaticStr};
+
+use crate::errors::ArgError;
+use crate::exec::Clock;
 
 #[derive(StructOpt)]
 pub(crate) struct Cli {
-    /// code file (only supports c++, py, and java)
+    /// code file (c, c++, py, java, rust, go, js, executables or scripts with a `#!` line)
+    /// or a command like `"python3 sol.py --fast"` (this goes for gen, ans & check too)
     #[structopt()]
     pub(crate) code: PathBuf,
 
@@ -11,7 +20,7 @@ pub(crate) struct Cli {
     #[structopt(long = "gen", short = "g")]
     pub(crate) gen: Option<PathBuf>,
 
-    /// correct code (required only if given generator)
+    /// correct code (required only if given generator, unless there's an interactor)
     #[structopt(long = "ans", short = "a", requires("gen"))]
     pub(crate) ans: Option<PathBuf>,
 
@@ -34,6 +43,15 @@ pub(crate) struct Cli {
     #[structopt(long = "check", short = "c", conflicts_with("fout"), conflicts_with("ans"))]
     pub(crate) checker: Option<PathBuf>,
 
+    /// interactor for interactive problems- its stdout goes to the code's stdin
+    /// & the other way around (it gets the path to the test input as its last argument
+    /// and its exit code is the verdict: 0 for AC, 1 for WA & 2 for PE)
+    #[structopt(
+        long = "interactor", short = "I",
+        conflicts_with_all(&["fout", "ans", "checker", "prog-fin", "prog-fout"])
+    )]
+    pub(crate) interactor: Option<PathBuf>,
+
     /// note: won't be used if `fin` & `fout` are normal files
     /// the format string for the input files
     /// (occurrences of `{}` will be replaced with numbers starting from 1)
@@ -81,14 +99,167 @@ pub(crate) struct Cli {
     #[structopt(long = "prog-stderr")]
     pub(crate) prog_stderr: bool,
 
+    /// don't rerun failed tests with a sanitized build of the code file
+    /// (normally c & c++ get rebuilt with `-fsanitize=address,undefined` & `_GLIBCXX_DEBUG`
+    /// when they get a WA or RE, to show you what went wrong)
+    #[structopt(long = "no-sanitize")]
+    pub(crate) no_sanitize: bool,
+
+    /// how many test cases to run at once (`0` for one per cpu core)
+    /// (each one gets its own directory to run in, so `prog-fin` & `prog-fout` still work)
+    #[structopt(long = "jobs", short = "j", default_value = "1")]
+    pub(crate) jobs: usize,
+
+    /// runs the programs in a sandbox, for code you don't trust (only on linux)-
+    /// they get their own directory, no network, a cap on how many processes they can make
+    /// and they get a security violation if they try to mess with the system
+    #[structopt(long = "sandbox")]
+    pub(crate) sandbox: bool,
+
+    /// time limit in seconds, e.g. `--time-limit 2` or `--time-limit gen=10`
+    /// (a bare value is for the code file, prefix with `gen=`, `ans=`, `checker=` or `interactor=`
+    /// for the others)
+    #[structopt(long = "time-limit", number_of_values = 1, parse(try_from_str = parse_prog_opt))]
+    pub(crate) time_limit: Vec<ProgOpt<f64>>,
+
+    /// what the time limit is measured with- `wall` for real time, or `cpu` for the time
+    /// the program actually spends on the cpu (user + system), which is what most judges go by
+    #[structopt(long = "clock", default_value = "wall", possible_values = &["wall", "cpu"])]
+    pub(crate) clock: Clock,
+
+    /// memory limit in megabytes, given the same way as `time-limit`
+    /// (it goes by the memory the program actually uses- on linux it gets killed as soon as
+    /// it goes over, on other unixes it's checked once it's done & on windows it isn't at all)
+    #[structopt(long = "memory-limit", number_of_values = 1, parse(try_from_str = parse_prog_opt))]
+    pub(crate) memory_limit: Vec<ProgOpt<u64>>,
+
+    /// output limit in megabytes for both stdout & stderr, given the same way as `time-limit`
+    #[structopt(long = "output-limit", number_of_values = 1, parse(try_from_str = parse_prog_opt))]
+    pub(crate) output_limit: Vec<ProgOpt<u64>>,
+
+    /// flags for the compiler, e.g. `--compile-flags "-O2 -DLOCAL"`
+    /// (a bare value is for the code file, prefix with `gen=`, `ans=`, `checker=` or `interactor=`
+    /// for the others)
+    #[structopt(
+        long = "compile-flags", number_of_values = 1, allow_hyphen_values = true,
+        parse(try_from_str = parse_prog_opt)
+    )]
+    pub(crate) compile_flags: Vec<ProgOpt<String>>,
+
+    /// the compiler for c & c++ (e.g. `--compiler clang++` or `--compiler ans=g++-13`),
+    /// given the same way as `compile-flags`
+    #[structopt(long = "compiler", number_of_values = 1, parse(try_from_str = parse_prog_opt))]
+    pub(crate) compiler: Vec<ProgOpt<String>>,
+
+    /// the language standard, e.g. `--std c++20` (c++ uses `c++17` & c uses `c11` by default)
+    #[structopt(long = "std", number_of_values = 1, parse(try_from_str = parse_prog_opt))]
+    pub(crate) std: Vec<ProgOpt<String>>,
+
+    /// the optimization level that goes after `-O`, e.g. `--opt 0` or `--opt ans=3` (default `2`)
+    #[structopt(long = "opt", number_of_values = 1, parse(try_from_str = parse_prog_opt))]
+    pub(crate) opt: Vec<ProgOpt<String>>,
+
+    /// macros to define when compiling, e.g. `-D LOCAL` or `-D gen=N=5`
+    /// (can be given more than once)
+    #[structopt(
+        long = "define", short = "D", number_of_values = 1, parse(try_from_str = parse_prog_opt)
+    )]
+    pub(crate) defines: Vec<ProgOpt<String>>,
+
+    /// arguments for the program itself, given the same way as `compile-flags`
+    #[structopt(
+        long = "run-args", number_of_values = 1, allow_hyphen_values = true,
+        parse(try_from_str = parse_prog_opt)
+    )]
+    pub(crate) run_args: Vec<ProgOpt<String>>,
+
+    /// options for the JVM when running java, e.g. `--jvm-opts "-Xss1g -Xmx512m"`
+    /// (given the same way as `compile-flags`)
+    #[structopt(
+        long = "jvm-opts", number_of_values = 1, allow_hyphen_values = true,
+        parse(try_from_str = parse_prog_opt)
+    )]
+    pub(crate) jvm_opts: Vec<ProgOpt<String>>,
+
+    /// the python interpreter to use, e.g. `--python pypy3` or `--python ans=python3`
+    /// (can be a command, a path to an interpreter or a virtualenv directory)
+    #[structopt(long = "python", number_of_values = 1, parse(try_from_str = parse_prog_opt))]
+    pub(crate) python: Vec<ProgOpt<String>>,
+
+    /// language definitions to use on top of the built-in ones
+    /// (defaults to `languages.toml` in aaargh's config directory, e.g. `~/.config/aargh`)
+    #[structopt(long = "lang-config")]
+    pub(crate) lang_config: Option<PathBuf>,
+
+    /// compile everything from scratch instead of reusing builds from earlier runs
+    #[structopt(long = "no-cache")]
+    pub(crate) no_cache: bool,
+
+    /// wipes all the builds saved from earlier runs before doing anything
+    #[structopt(long = "clear-cache")]
+    pub(crate) clear_cache: bool,
+
     #[structopt(subcommand)]
     pub(crate) run_options: Option<RunOptions>
 }
 
 // https://docs.rs/structopt/latest/structopt/#external-subcommands
-#[derive(Debug, PartialEq, StructOpt)]
+#[derive(Debug, Clone, PartialEq, StructOpt)]
 pub(crate) enum RunOptions {
     None,
     #[structopt(external_subcommand)]
     Some(Vec<std::ffi::OsString>)
 }
+
+/// the different programs aaargh might end up running
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumIter, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum Prog { Code, Gen, Ans, Checker, Interactor }
+
+impl Prog {
+    pub(crate) fn desc(&self) -> &str {
+        match self {
+            Prog::Code => "solution",
+            Prog::Gen => "generator",
+            Prog::Ans => "brute solution",
+            Prog::Checker => "checker",
+            Prog::Interactor => "interactor"
+        }
+    }
+}
+
+/// an option that can be given separately for each `Prog`
+#[derive(Debug, Clone)]
+pub(crate) struct ProgOpt<T> {
+    pub(crate) prog: Prog,
+    pub(crate) val: T
+}
+
+fn parse_prog_opt<T: FromStr>(s: &str) -> Result<ProgOpt<T>, ArgError>
+where T::Err: std::fmt::Display {
+    let (prog, val) = match s.split_once('=') {
+        Some((p, v)) => match Prog::iter().find(|l| <&str>::from(*l) == p) {
+            Some(prog) => (prog, v),
+            None => (Prog::Code, s)
+        },
+        None => (Prog::Code, s)
+    };
+    match val.parse::<T>() {
+        Ok(val) => Ok(ProgOpt { prog, val }),
+        Err(e) => Err(ArgError { err: format!("couldn't parse '{}': {}", val, e) })
+    }
+}
+
+/// gets the value of an option for a given program (later occurrences win)
+pub(crate) fn for_prog<T: Clone>(opts: &[ProgOpt<T>], prog: Prog) -> Option<T> {
+    opts.iter().rev().find(|o| o.prog == prog).map(|o| o.val.clone())
+}
+
+/// gets every value of an option for a given program, split up by whitespace
+pub(crate) fn words_for_prog(opts: &[ProgOpt<String>], prog: Prog) -> Vec<OsString> {
+    opts.iter()
+        .filter(|o| o.prog == prog)
+        .flat_map(|o| o.val.split_whitespace())
+        .map(OsString::from)
+        .collect()
+}
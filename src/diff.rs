@@ -3,22 +3,69 @@ use std::iter::zip;
 use colored::Colorize;
 use regex::Regex;
 
+use crate::exec::{escape, lines_of};
+use crate::verdict::Verdict;
+
 fn writeln(s: &impl Display, mut out: impl std::io::Write) {
     if let Err(e) = writeln!(out, "{}", s) {
         eprintln!("wtf why can't i write: {}", e);
     }
 }
 
+/// the grading options from the command line
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct DiffOpts {
+    pub(crate) whitespace_matters: bool,
+    pub(crate) str_case: bool,
+    pub(crate) one_abort: bool
+}
+
+/// compares the output with the answer as text if both of them are valid utf-8,
+/// and byte for byte if they aren't
+pub(crate) fn diff_bytes(
+    given: &[u8], actual: &[u8],
+    opts: DiffOpts,
+    mut out: impl std::io::Write
+) -> Verdict {
+    if let (Ok(g), Ok(a)) = (std::str::from_utf8(given), std::str::from_utf8(actual)) {
+        return diff_lines(g.lines(), a.lines(), opts, out);
+    }
+
+    let g_vec: Vec<&[u8]> = lines_of(given).collect();
+    let a_vec: Vec<&[u8]> = lines_of(actual).collect();
+    if g_vec == a_vec {
+        return Verdict::Accepted;
+    }
+    writeln(&"output isn't valid text, so it has to match the answer exactly".red(), &mut out);
+    let line_num = zip(&g_vec, &a_vec).position(|(g, a)| g != a);
+    match line_num {
+        Some(i) => writeln(&format!(
+            "mismatch at line {}:\ngiven line: '{}'\nactual line: '{}'",
+            i + 1, escape(g_vec[i]), escape(a_vec[i])
+        ).red(), &mut out),
+        None => {
+            let thing = if a_vec.len() > g_vec.len() {
+                ("answer", "output")
+            } else { ("output", "answer") };
+            writeln(&format!("{} has more lines than the {}", thing.0, thing.1).red(), &mut out);
+        }
+    }
+    Verdict::WrongAnswer
+}
+
 pub(crate) fn diff_lines<'a>(
     given: impl IntoIterator<Item = &'a str>,
     actual: impl IntoIterator<Item = &'a str>,
-    whitespace_matters: bool, str_case: bool, one_abort: bool,
+    opts: DiffOpts,
     mut out: impl std::io::Write
-) -> bool {
+) -> Verdict {
+    let DiffOpts { whitespace_matters, str_case, one_abort } = opts;
     let mut g_vec: Vec<&str> = given.into_iter().collect();
     let mut a_vec: Vec<&str> = actual.into_iter().collect();
 
+    // differences in the actual values vs. differences in how they're laid out
     let mut different = false;
+    let mut bad_fmt = false;
 
     if !whitespace_matters {
         while let Some(l) = g_vec.last() {
@@ -37,7 +84,14 @@ pub(crate) fn diff_lines<'a>(
     }
 
     if g_vec.len() != a_vec.len() {
-        different = true;
+        let extra = if g_vec.len() > a_vec.len() {
+            &g_vec[a_vec.len()..]
+        } else { &a_vec[g_vec.len()..] };
+        if extra.iter().all(|l| l.trim().is_empty()) {
+            bad_fmt = true;
+        } else {
+            different = true;
+        }
         writeln(&format!("{}", "mismatch:".red()), &mut out);
         let thing = if a_vec.len() > g_vec.len() {
             ("answer", "output")
@@ -52,9 +106,6 @@ pub(crate) fn diff_lines<'a>(
     let mut line_num = 0;
     for (g, a) in zip(g_vec, a_vec) {
         line_num += 1;
-        if g == a {
-            continue;
-        }
 
         let mut g = g.to_string();
         let mut a = a.to_string();
@@ -62,6 +113,9 @@ pub(crate) fn diff_lines<'a>(
             g = g.to_lowercase();
             a = a.to_lowercase();
         }
+        if g == a {
+            continue;
+        }
 
         let go = Output::parse(&g);
         let ao = Output::parse(&a);
@@ -69,11 +123,15 @@ pub(crate) fn diff_lines<'a>(
             continue;
         }
 
-        different = true;
         writeln(&format!(
             "mismatch with {}s at line {line_num}:", go.detected_type()
         ).red(), &mut out);
 
+        if go == ao {
+            bad_fmt = true;
+        } else {
+            different = true;
+        }
         if std::mem::discriminant(&go) != std::mem::discriminant(&ao) {
             let tp = format!(
                 "output types don't match ({} should be {})",
@@ -127,7 +185,14 @@ pub(crate) fn diff_lines<'a>(
             break;
         }
     }
-    different
+
+    if different {
+        Verdict::WrongAnswer
+    } else if bad_fmt {
+        Verdict::PresentationError
+    } else {
+        Verdict::Accepted
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -182,7 +247,7 @@ fn iter_diff<T: PartialEq>(
     actual: impl IntoIterator<Item = T>
 ) -> Vec<Diff<T>> {
     let mut res = Vec::new();
-    for (i, (g, a)) in given.into_iter().zip(actual.into_iter()).enumerate() {
+    for (i, (g, a)) in given.into_iter().zip(actual).enumerate() {
         if g != a {
             res.push(Diff { given: g, actual: a, pos: i });
         }
@@ -1,5 +1,8 @@
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter, Result};
+use regex::Regex;
+
+use crate::cli::Prog;
 use crate::exec;
 
 #[derive(Debug, Clone)]
@@ -31,30 +34,160 @@ impl Error for BadLangError {  }
 
 impl Display for BadLangError {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        write!(f, "the language {} doesn't exist", self.ext)
+        write!(
+            f, "the language {} doesn't exist (and there's no #! line saying what to run it with)",
+            self.ext
+        )
     }
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct LangNotFoundError { pub(crate) lang: exec::Lang }
+pub(crate) struct LangNotFoundError { pub(crate) lang: String }
 
 impl Error for LangNotFoundError {  }
 
 impl Display for LangNotFoundError {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        let name: &'static str = self.lang.into();  // cursed
-        write!(f, "{} not found on the system path", name)
+        write!(f, "{} not found on the system path", self.lang)
     }
 }
 
 #[derive(Debug, Clone)]
-pub(crate) struct RuntimeError { pub(crate) err: String }
+pub(crate) struct SpawnError { pub(crate) program: std::path::PathBuf, pub(crate) err: String }
+
+impl Error for SpawnError {  }
+
+impl Display for SpawnError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "couldn't start {}: {}", exec::path_str(&self.program), self.err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct RuntimeError {
+    pub(crate) err: String,
+    /// the exit code or signal the program died with
+    pub(crate) exit: exec::Exit
+}
 
 impl Error for RuntimeError {  }
 
 impl Display for RuntimeError {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        write!(f, "error while executing script:\n{}", self.err)
+        write!(f, "error while executing script ({}):\n{}", self.exit, self.err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct CompileError {
+    /// which program didn't compile
+    pub(crate) prog: Prog,
+    pub(crate) lang: String,
+    /// everything the compiler printed
+    pub(crate) output: String
+}
+
+/// how many of the compiler's errors get shown
+const SHOWN_ERRORS: usize = 5;
+
+impl CompileError {
+    /// picks the errors out of what the compiler printed, as `file:line: message`
+    pub(crate) fn errors(&self) -> Vec<String> {
+        // gcc, clang & javac all go `file:line[:col]: error: message`
+        let c_like = Regex::new(r"^(.+?:\d+(?::\d+)?): (?:fatal )?error: (.*)$").unwrap();
+        // go leaves out the `error`
+        let go = Regex::new(r"^(.+?\.go:\d+:\d+): (.*)$").unwrap();
+        // rustc puts the location on the line after the message
+        let rust = Regex::new(r"^error(?:\[\w+\])?: (.*)$").unwrap();
+        let rust_loc = Regex::new(r"^\s*--> (.+:\d+:\d+)$").unwrap();
+
+        let mut res = Vec::new();
+        let mut rust_msg: Option<String> = None;
+        for line in self.output.lines() {
+            if let Some(msg) = rust_msg.take() {
+                match rust_loc.captures(line) {
+                    Some(c) => {
+                        res.push(format!("{}: {}", &c[1], msg));
+                        continue;
+                    }
+                    None => res.push(msg)
+                }
+            }
+            if let Some(c) = c_like.captures(line).or_else(|| go.captures(line)) {
+                res.push(format!("{}: {}", &c[1], &c[2]));
+            } else if let Some(c) = rust.captures(line) {
+                // not an actual error, just rustc summing up
+                if !c[1].starts_with("aborting due to") {
+                    rust_msg = Some(c[1].to_string());
+                }
+            }
+        }
+        res.extend(rust_msg);
+        res
+    }
+}
+
+impl Error for CompileError {  }
+
+impl Display for CompileError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "couldn't compile the {} ({}):", self.prog.desc(), self.lang)?;
+        let errors = self.errors();
+        if errors.is_empty() {
+            // no idea what the compiler's on about, so just show all of it
+            return write!(f, "\n{}", self.output.trim_end());
+        }
+        for e in errors.iter().take(SHOWN_ERRORS) {
+            write!(f, "\n{}", e)?;
+        }
+        if errors.len() > SHOWN_ERRORS {
+            write!(f, "\n(and {} more)", errors.len() - SHOWN_ERRORS)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct TimeLimitError { pub(crate) limit: f64, pub(crate) clock: exec::Clock }
+
+impl Error for TimeLimitError {  }
+
+impl Display for TimeLimitError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "time limit of {} s ({} time) exceeded", self.limit, <&str>::from(self.clock))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct MemoryLimitError { pub(crate) limit: u64 }
+
+impl Error for MemoryLimitError {  }
+
+impl Display for MemoryLimitError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "memory limit of {} MB exceeded", self.limit)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct OutputLimitError { pub(crate) limit: u64 }
+
+impl Error for OutputLimitError {  }
+
+impl Display for OutputLimitError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "output limit of {} MB exceeded", self.limit)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SecurityError { pub(crate) err: String }
+
+impl Error for SecurityError {  }
+
+impl Display for SecurityError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "the program tried to do something the sandbox doesn't allow:\n{}", self.err)
     }
 }
 
@@ -63,7 +196,13 @@ pub(crate) enum ExecError {
     PathNotFound(PathNotFound),
     BadLang(BadLangError),
     LangNotFound(LangNotFoundError),
-    RuntimeError(RuntimeError)
+    Spawn(SpawnError),
+    RuntimeError(RuntimeError),
+    CompileError(CompileError),
+    TimeLimit(TimeLimitError),
+    MemoryLimit(MemoryLimitError),
+    OutputLimit(OutputLimitError),
+    Security(SecurityError)
 }
 
 impl ExecError {
@@ -75,12 +214,38 @@ impl ExecError {
         Self::BadLang(BadLangError { ext: ext.to_string() })
     }
 
-    pub(crate) fn lang_not_found(lang: exec::Lang) -> ExecError {
-        Self::LangNotFound(LangNotFoundError { lang })
+    pub(crate) fn lang_not_found(lang: &str) -> ExecError {
+        Self::LangNotFound(LangNotFoundError { lang: lang.to_string() })
+    }
+
+    pub(crate) fn spawn(program: std::path::PathBuf, err: &std::io::Error) -> ExecError {
+        Self::Spawn(SpawnError { program, err: err.to_string() })
+    }
+
+    pub(crate) fn runtime_error(err: &str, exit: exec::Exit) -> ExecError {
+        Self::RuntimeError(RuntimeError { err: err.to_string(), exit })
+    }
+
+    pub(crate) fn compile_error(prog: Prog, lang: &str, output: &str) -> ExecError {
+        Self::CompileError(CompileError {
+            prog, lang: lang.to_string(), output: output.to_string()
+        })
+    }
+
+    pub(crate) fn time_limit(limit: f64, clock: exec::Clock) -> ExecError {
+        Self::TimeLimit(TimeLimitError { limit, clock })
+    }
+
+    pub(crate) fn memory_limit(limit: u64) -> ExecError {
+        Self::MemoryLimit(MemoryLimitError { limit })
+    }
+
+    pub(crate) fn output_limit(limit: u64) -> ExecError {
+        Self::OutputLimit(OutputLimitError { limit })
     }
 
-    pub(crate) fn runtime_error(err: &str) -> ExecError {
-        Self::RuntimeError(RuntimeError { err: err.to_string() })
+    pub(crate) fn security(err: &str) -> ExecError {
+        Self::Security(SecurityError { err: err.to_string() })
     }
 }
 
@@ -92,7 +257,13 @@ impl Display for ExecError {
             ExecError::BadLang(e) => Display::fmt(e, f),
             ExecError::LangNotFound(e) => Display::fmt(e, f),
             ExecError::PathNotFound(e) => Display::fmt(e, f),
-            ExecError::RuntimeError(e) => Display::fmt(e, f)
+            ExecError::Spawn(e) => Display::fmt(e, f),
+            ExecError::RuntimeError(e) => Display::fmt(e, f),
+            ExecError::CompileError(e) => Display::fmt(e, f),
+            ExecError::TimeLimit(e) => Display::fmt(e, f),
+            ExecError::MemoryLimit(e) => Display::fmt(e, f),
+            ExecError::OutputLimit(e) => Display::fmt(e, f),
+            ExecError::Security(e) => Display::fmt(e, f)
         }
     }
 }
@@ -1,188 +1,805 @@
-use std::path::{PathBuf};
-use std::io::{ErrorKind, Write};
-use std::process::{Command, Stdio};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::io::{ErrorKind, Read, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
 
-use strum::IntoEnumIterator;
-use strum_macros::{EnumIter, IntoStaticStr};
 use is_executable::IsExecutable;
+use strum_macros::{EnumString, IntoStaticStr};
 
 use crate::RunOptions;
+use crate::cli::Prog;
+use crate::cache::{Build, Cache};
 use crate::errors::*;
+use crate::lang::{java_classes, Lang, Registry, Vars};
 
-#[derive(Debug, Copy, Clone, EnumIter, IntoStaticStr)]
-pub(crate) enum Lang { Python, Java, Cpp }
+/// what a program printed is kept as raw bytes since nothing makes it print valid utf-8,
+/// use `escape` for showing it
+#[derive(Debug, Clone)]
+pub(crate) struct ProgRes {
+    pub(crate) stdout: Vec<u8>,
+    pub(crate) stderr: Vec<u8>,
+    /// wall-clock time in seconds
+    pub(crate) time: f64,
+    /// user + system cpu time in seconds (`None` if the platform can't tell us)
+    pub(crate) cpu: Option<f64>,
+    /// peak resident memory in kilobytes (`None` if the platform can't tell us)
+    pub(crate) memory: Option<u64>
+}
 
-impl Lang {
-    fn valid_ext(&self) -> Vec<&str> {
-        match self {
-            Lang::Python => vec!["py"],
-            Lang::Java => vec!["java"],
-            Lang::Cpp => vec!["cpp", "cc", "cxx", "c++"]
+/// how a program ended
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Exit {
+    Code(i32),
+    /// killed by a signal (only happens on unix)
+    Signal(i32)
+}
+
+impl From<ExitStatus> for Exit {
+    #[cfg(unix)]
+    fn from(status: ExitStatus) -> Exit {
+        use std::os::unix::process::ExitStatusExt;
+        match status.signal() {
+            Some(sig) => Exit::Signal(sig),
+            None => Exit::Code(status.code().unwrap_or(-1))
         }
     }
 
-    fn file_lang(file: &PathBuf) -> Option<Lang> {
-        let ext = path_ext(file);
-        if ext.is_none() {
-            return None;
-        }
-        let ext = ext.unwrap();
-        for l in Lang::iter() {
-            if l.valid_ext().contains(&ext) {
-                return Some(l);
+    #[cfg(not(unix))]
+    fn from(status: ExitStatus) -> Exit {
+        Exit::Code(status.code().unwrap_or(-1))
+    }
+}
+
+impl std::fmt::Display for Exit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Exit::Signal(sig) => match signal_name(*sig) {
+                Some((name, desc)) => write!(f, "{}: {}", name, desc),
+                None => write!(f, "killed by signal {}", sig)
+            },
+            Exit::Code(code) => match crash_code_name(*code) {
+                Some(desc) => write!(f, "exit code {:#x}: {}", code, desc),
+                None => write!(f, "exit code {}", code)
             }
         }
-        None
     }
 }
 
+#[cfg(unix)]
+fn signal_name(sig: i32) -> Option<(&'static str, &'static str)> {
+    Some(match sig {
+        libc::SIGSEGV => ("SIGSEGV", "segmentation fault"),
+        libc::SIGFPE => ("SIGFPE", "floating point exception (division by zero?)"),
+        libc::SIGABRT => ("SIGABRT", "aborted (failed assert?)"),
+        libc::SIGBUS => ("SIGBUS", "bus error"),
+        libc::SIGILL => ("SIGILL", "illegal instruction"),
+        libc::SIGKILL => ("SIGKILL", "killed"),
+        libc::SIGTERM => ("SIGTERM", "terminated"),
+        libc::SIGINT => ("SIGINT", "interrupted"),
+        libc::SIGPIPE => ("SIGPIPE", "broken pipe"),
+        libc::SIGXCPU => ("SIGXCPU", "cpu time limit exceeded"),
+        libc::SIGXFSZ => ("SIGXFSZ", "file size limit exceeded"),
+        libc::SIGSYS => ("SIGSYS", "bad system call"),
+        libc::SIGTRAP => ("SIGTRAP", "trace/breakpoint trap"),
+        _ => return None
+    })
+}
+
+#[cfg(not(unix))]
+fn signal_name(_sig: i32) -> Option<(&'static str, &'static str)> {
+    None
+}
+
+/// windows doesn't have signals, crashes just show up as these exit codes
+fn crash_code_name(code: i32) -> Option<&'static str> {
+    if !cfg!(windows) {
+        return None;
+    }
+    Some(match code as u32 {
+        0xC0000005 => "access violation",
+        0xC00000FD => "stack overflow",
+        0xC0000094 => "integer division by zero",
+        0xC000008E => "floating point division by zero",
+        0xC0000409 => "stack buffer overrun",
+        0xC0000374 => "heap corruption",
+        _ => return None
+    })
+}
+
+/// what the time limit is measured with
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, EnumString, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum Clock {
+    /// plain old real time, like a stopwatch
+    #[default]
+    Wall,
+    /// the time the program actually spent on the cpu (user + system), like most judges use
+    Cpu
+}
+
+/// resource limits for a single execution (`None` means no limit)
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Limits {
+    /// time limit in seconds
+    pub(crate) time: Option<f64>,
+    /// what `time` is measured with
+    pub(crate) clock: Clock,
+    /// resident memory limit in megabytes (not enforced on windows)
+    pub(crate) memory: Option<u64>,
+    /// limit for stdout & stderr (each) in megabytes
+    pub(crate) output: Option<u64>
+}
+
+/// where a program got built & what it takes to run it
 #[derive(Debug, Clone)]
-pub(crate) struct ProgRes {
-    pub(crate) stdout: String,
-    pub(crate) stderr: String,
-    pub(crate) time: f64
+pub(crate) struct Built {
+    dir: PathBuf,
+    /// the copy of the code file that actually got compiled, if it had to be renamed (for java)
+    renamed: Option<PathBuf>,
+    /// the main class (for java)
+    class: String
+}
+
+/// a program along with everything needed to run it
+#[derive(Debug, Clone)]
+pub(crate) struct Program<'a> {
+    /// what the program's for
+    pub(crate) role: Prog,
+    /// path with code, has to be an executable or in one of the languages in `langs`
+    /// (or the command itself if `command` is set)
+    pub(crate) code: PathBuf,
+    /// a command to run as-is instead of a file (see `parse_command`)
+    pub(crate) command: Option<Vec<OsString>>,
+    /// flags for the compiler (ignored for interpreted languages & executables)
+    pub(crate) compile_flags: Vec<OsString>,
+    /// the language standard for `{std}` (`None` for the language's default)
+    pub(crate) std: Option<String>,
+    /// the optimization level for `{opt}` (`None` for the language's default)
+    pub(crate) opt: Option<String>,
+    /// macros to define for `{defines}`, like `LOCAL` or `N=5`
+    pub(crate) defines: Vec<OsString>,
+    /// arguments for the program itself
+    pub(crate) run_args: Vec<OsString>,
+    /// options for the JVM (only matters for java & friends)
+    pub(crate) jvm_opts: Vec<OsString>,
+    /// `RunOptions` from the main file- these go to the compiler if there is one
+    /// and to the program otherwise
+    pub(crate) options: RunOptions,
+    /// the program gets killed if it goes over these
+    pub(crate) limits: Limits,
+    /// the languages to pick from based on the file extension
+    pub(crate) langs: Registry,
+    /// where to keep compiled programs between runs (`None` to always compile)
+    pub(crate) cache: Option<&'a Cache>,
+    /// where the program gets compiled to if it isn't cached
+    /// (each program needs its own so they don't clobber each other)
+    pub(crate) build_dir: PathBuf,
+    /// the directory the program runs in (`None` for the current one)
+    /// note: `code` has to be an absolute path if this is set
+    pub(crate) workdir: Option<PathBuf>,
+    /// run it in the sandbox (only if `CAN_SANDBOX`)
+    pub(crate) sandbox: bool,
+    /// build it with the language's `debug_flags` (see `debug_build`)
+    pub(crate) debug: bool,
+    /// where it got built, shared between copies so they don't all have to work it out again
+    pub(crate) built: Arc<OnceLock<Built>>
+}
+
+impl Program<'_> {
+    /// a copy of the program that runs in `dir` instead
+    pub(crate) fn in_dir(&self, dir: &Path) -> std::io::Result<Self> {
+        let mut prog = self.clone();
+        // relative paths would be relative to the wrong directory
+        prog.code = std::path::absolute(&prog.code)?;
+        // this could make an argument that just happens to be a file name absolute,
+        // but that's a lot less likely than a command that runs some file in here
+        for w in prog.command.iter_mut().flatten() {
+            if Path::new(w).exists() {
+                *w = std::path::absolute(&w)?.into_os_string();
+            }
+        }
+        prog.workdir = Some(dir.to_path_buf());
+        Ok(prog)
+    }
+
+    /// a copy of the program built with sanitizers & the like for tracking down what went wrong
+    /// (`None` if its language doesn't have `debug_flags`)
+    pub(crate) fn debug_build(&self) -> Option<Self> {
+        if self.command.is_some() {
+            return None;
+        }
+        self.langs.file_lang(&self.code)?.debug_flags.as_ref()?;
+        let mut prog = self.clone();
+        prog.debug = true;
+        prog.built = Arc::default();
+        let mut build_dir = prog.build_dir.into_os_string();
+        build_dir.push("-debug");
+        prog.build_dir = build_dir.into();
+        // sanitizers slow things down & reserve a ridiculous amount of address space
+        prog.limits.time = prog.limits.time.map(|t| t * DEBUG_SLOWDOWN);
+        prog.limits.memory = None;
+        Some(prog)
+    }
 }
 
+/// how much more time a debug build gets than the time limit
+const DEBUG_SLOWDOWN: f64 = 5.0;
+
+/// whether there's a sandbox on this platform
+pub(crate) const CAN_SANDBOX: bool = cfg!(all(
+    target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")
+));
+
 /// executes some code from a path given input & whatever
 /// ### arguments:
-/// * code: path with code, only supports python 3, c++, and java
+/// * prog: the program to run
 /// * input: optional input to be passed into stdin
-/// * options: `RunOptions` from the main file, contains arguments
-///            to be passed to the created compiler/interpreter process
 /// * compiled: has this been compiled already?
 ///   * if it's an interpreted language, has no effect
 ///   * if it's compiled, this will just run the relevant execution command
 pub(crate) fn exec(
-    code: &PathBuf, input: &str,
-    options: &RunOptions, compiled: bool
+    prog: &Program, input: &[u8], compiled: bool
 ) -> Result<ProgRes, ExecError> {
-    if !code.is_file() {
-        return Err(ExecError::path_not_found(code.clone()));
+    let limits = &prog.limits;
+    let mut child = spawn(command(prog, compiled)?, prog)?;
+    let start = Instant::now();
+
+    let mut lines = Vec::new();
+    for l in lines_of(input) {
+        lines.extend_from_slice(l);
+        lines.push(b'\n');
     }
+    // the input gets written while the output's being read, otherwise a program that prints
+    // as it reads fills up its stdout pipe and waits on us while we wait on it
+    let feeder = feed(child.stdin.take().unwrap(), lines);
+    // the pipes have to be drained while we wait, otherwise a chatty program blocks forever
+    let too_long = Arc::new(AtomicBool::new(false));
+    let stdout = drain(child.stdout.take().unwrap(), limits.output, too_long.clone(), |_| {});
+    let stderr = drain(child.stderr.take().unwrap(), limits.output, too_long.clone(), |_| {});
+
+    let res = finish(&mut child, start, limits, &too_long, stdout, stderr, prog.sandbox)?;
+    feeder.join().unwrap();
+    Ok(res)
+}
+
+/// who said what in an interactive run
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Speaker { Solution, Interactor }
+
+/// everything that got sent back and forth in an interactive run, in order
+pub(crate) type Transcript = Vec<(Speaker, Vec<u8>)>;
+
+/// how an interactive run went
+#[derive(Debug)]
+pub(crate) struct Interaction {
+    /// how the solution did (its stdout is everything it sent to the interactor)
+    pub(crate) sol: Result<ProgRes, ExecError>,
+    /// how the interactor did, its exit code is what it thinks of the solution
+    pub(crate) interactor: Result<ProgRes, ExecError>,
+    pub(crate) transcript: Transcript
+}
 
-    let lang = Lang::file_lang(code);
+/// runs a solution with its stdin & stdout hooked up to an interactor's stdout & stdin
+/// (the interactor gets the test input through a file, whose path is tacked onto its arguments)
+pub(crate) fn interact(
+    sol: &Program, interactor: &Program, input: &[u8], compiled: bool
+) -> Result<Interaction, ExecError> {
+    let sol_cmd = command(sol, compiled)?;
+    let mut int_cmd = command(interactor, compiled)?;
+    let dir = interactor.workdir.as_ref().unwrap_or(&interactor.build_dir);
+    std::fs::create_dir_all(dir).expect("couldn't make the build directory");
+    let input_file = dir.join("test.in");
+    std::fs::write(&input_file, input).expect("couldn't write the test input");
+    int_cmd.arg(&input_file);
+
+    let mut sol_child = spawn(sol_cmd, sol)?;
+    let mut int_child = match spawn(int_cmd, interactor) {
+        Ok(c) => c,
+        Err(e) => {
+            sol_child.kill().ok();
+            sol_child.wait().ok();
+            return Err(e);
+        }
+    };
+    let start = Instant::now();
+
+    // everything goes through us so we can write it down along the way
+    let transcript: Arc<Mutex<Transcript>> = Arc::default();
+    let relay = |speaker: Speaker, mut to: ChildStdin| {
+        let transcript = transcript.clone();
+        move |chunk: &[u8]| {
+            // the lock can't be held while writing, or a full pipe would hold up the other side
+            {
+                let mut transcript = transcript.lock().unwrap();
+                match transcript.last_mut() {
+                    Some((s, said)) if *s == speaker => said.extend_from_slice(chunk),
+                    _ => transcript.push((speaker, chunk.to_vec()))
+                }
+            }
+            // the other one might've quit already, that's on them
+            to.write_all(chunk).and_then(|_| to.flush()).ok();
+        }
+    };
+    let sol_long = Arc::new(AtomicBool::new(false));
+    let int_long = Arc::new(AtomicBool::new(false));
+    let sol_out = drain(
+        sol_child.stdout.take().unwrap(), sol.limits.output, sol_long.clone(),
+        relay(Speaker::Solution, int_child.stdin.take().unwrap())
+    );
+    let int_out = drain(
+        int_child.stdout.take().unwrap(), interactor.limits.output, int_long.clone(),
+        relay(Speaker::Interactor, sol_child.stdin.take().unwrap())
+    );
+    let sol_err = drain(
+        sol_child.stderr.take().unwrap(), sol.limits.output, sol_long.clone(), |_| {}
+    );
+    let int_err = drain(
+        int_child.stderr.take().unwrap(), interactor.limits.output, int_long.clone(), |_| {}
+    );
+
+    let (sol_res, int_res) = std::thread::scope(|s| {
+        let int_res = s.spawn(
+            || finish(
+                &mut int_child, start, &interactor.limits, &int_long,
+                int_out, int_err, interactor.sandbox
+            )
+        );
+        let sol_res = finish(
+            &mut sol_child, start, &sol.limits, &sol_long, sol_out, sol_err, sol.sandbox
+        );
+        (sol_res, int_res.join().unwrap())
+    });
+    let transcript = std::mem::take(&mut *transcript.lock().unwrap());
+    Ok(Interaction { sol: sol_res, interactor: int_res, transcript })
+}
+
+/// splits up something like `python3 gen.py --n 10` to be run as a command
+/// (`None` if it's a file, or doesn't look like a command, so it gets treated as a path)
+pub(crate) fn parse_command(code: &Path) -> Option<Vec<OsString>> {
+    if code.exists() {
+        return None;
+    }
+    let words = shlex::split(code.to_str()?)?;
+    // otherwise a typo (or a file name with a space in it) would get run as a command
+    if !can_run(words.first()?) {
+        return None;
+    }
+    Some(words.into_iter().map(OsString::from).collect())
+}
+
+/// whether a command's something that can actually be found (a file, or something in `PATH`)
+pub(crate) fn can_run(cmd: &str) -> bool {
+    let path = Path::new(cmd);
+    if path.components().count() > 1 {
+        return path.is_file() && path.is_executable();
+    }
+    on_path(cmd)
+}
+
+/// whether there's an executable with this name in one of the directories in `PATH`
+fn on_path(name: &str) -> bool {
+    if Path::new(name).components().count() != 1 {
+        return false;
+    }
+    let paths = match std::env::var_os("PATH") {
+        Some(p) => p,
+        None => return false
+    };
+    let exe = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&paths)
+        .any(|d| d.join(name).is_executable() || d.join(&exe).is_executable())
+}
+
+/// gets the command that runs a program, compiling it first if it has to be
+fn command(prog: &Program, compiled: bool) -> Result<Command, ExecError> {
+    let code = prog.code.as_path();
+    if prog.command.is_none() && !code.is_file() {
+        return Err(ExecError::path_not_found(code.to_path_buf()));
+    }
 
     let empty = Vec::new();  // this is fricking stupid
-    let options = match options {
+    let options = match &prog.options {
         RunOptions::Some(a) => a,
         RunOptions::None => &empty
     };
 
-    // note to self: https://doc.rust-lang.org/std/time/struct.Instant.html
     let file = path_str(code);
-    let mut cmd;
-    match lang {
-        None => {
+    // a command's "extension" is just whatever comes after its last dot
+    let lang = if prog.command.is_some() { None } else { prog.langs.file_lang(code) };
+    let mut cmd = match (&prog.command, lang) {
+        (Some(words), _) => {
+            let mut cmd = Command::new(&words[0]);
+            cmd.args(&words[1..]).args(&prog.run_args).args(options);
+            cmd
+        }
+        (None, None) => {
             if !code.is_executable() {
                 let ext = path_ext(code).unwrap_or("");
                 return Err(ExecError::bad_lang(ext));
             }
-            cmd = Command::new(format!("./{}", path_str(code)));
-            cmd.args(options);
-        }
-        Some(Lang::Python) => {
-            let cmds = vec!["py", "python", "python3"];
-            let cmd_use = cmds.iter()
-                .find(|c| cmd_exists(c))
-                .ok_or(ExecError::lang_not_found(Lang::Python))?;
-
-            cmd = Command::new(cmd_use);
-            cmd.arg(&file).args(options);
-        }
-        Some(Lang::Java) => {
-            let runner = "java";
-            if !compiled {
-                let compiler = "javac";
-                if !cmd_exists(runner) || !cmd_exists(compiler) {
-                    return Err(ExecError::lang_not_found(Lang::Java));
-                }
-                let compile_res = Command::new(compiler)
-                    .arg(&file)
-                    .args(options)
-                    .spawn().expect("JAVA OH NO")
-                    // make sure compilation finishes first
-                    .wait().expect("bruh...");
-                if !compile_res.success() {
-                    return Err(ExecError::runtime_error("java compilation error"));
-                }
+            // joining an absolute path just gives back the absolute path
+            let mut cmd = Command::new(Path::new(".").join(code));
+            cmd.args(&prog.run_args).args(options);
+            cmd
+        }
+        (None, Some(lang)) => {
+            let mut flags = prog.compile_flags.clone();
+            if prog.debug {
+                let debug = lang.debug_flags.as_deref().unwrap_or("");
+                flags.extend(debug.split_whitespace().map(OsString::from));
+            }
+            let mut args = prog.run_args.clone();
+            if lang.compile.is_some() {
+                flags.extend(options.iter().cloned());
+            } else {
+                args.extend(options.iter().cloned());
             }
 
-            cmd = Command::new(runner);
-            cmd.arg(&file);
-        }
-        Some(Lang::Cpp) => {
-            let name = code.file_stem().unwrap().to_str().unwrap().trim();
-            if !compiled {
-                let compiler = "g++";
-                if !cmd_exists(compiler) {
-                    return Err(ExecError::lang_not_found(Lang::Cpp));
-                }
-                let compile_res = Command::new(compiler)
-                    .arg(&file)
-                    .arg("-o").arg(name)
-                    .args(options)
-                    .spawn().expect("C++ OH NO")
-                    .wait().expect("bruh...");
-                if !compile_res.success() {
-                    return Err(ExecError::runtime_error("cpp compilation error"));
+            let stem = code.file_stem().unwrap().to_str().unwrap().trim();
+            let std = prog.std.as_deref().or(lang.std.as_deref()).unwrap_or("");
+            let opt = prog.opt.as_deref().or(lang.opt.as_deref()).unwrap_or("");
+            // once it's compiled, copies of the program can just go straight to running it
+            let (built, build) = match prog.built.get() {
+                Some(b) if compiled => (b.clone(), None),
+                _ => locate(prog, lang, stem, &flags, std, opt)?
+            };
+            let file = built.renamed.as_deref().map_or(file, path_str);
+            let out = built.dir.join(format!("{}{}", stem, std::env::consts::EXE_SUFFIX));
+            let (dir, out, class) = (path_str(&built.dir), path_str(&out), built.class.as_str());
+            let vars = Vars {
+                file: &file, stem, dir: &dir, out: &out, class,
+                std, opt, defines: &prog.defines,
+                flags: &flags, jvm: &prog.jvm_opts, args: &args
+            };
+            if !compiled && !build.as_ref().is_some_and(Build::is_done) {
+                if let Some(mut compile) = lang.compile_cmd(&vars)? {
+                    // the compiler's output gets caught so it doesn't get mixed in with ours
+                    let compile_res = compile
+                        .stdin(Stdio::null())
+                        .output().expect("COMPILER OH NO");
+                    if !compile_res.status.success() {
+                        let output = [compile_res.stdout, compile_res.stderr].concat();
+                        // point at the actual code file, not the copy java made us make
+                        let output = escape(&output).replace(&file, &path_str(code));
+                        return Err(ExecError::compile_error(prog.role, &lang.name, &output));
+                    }
+                    if let Some(b) = &build {
+                        b.finish().expect("couldn't save the build");
+                    }
                 }
             }
+            prog.built.set(built.clone()).ok();
+            lang.run_cmd(&vars)?
+        }
+    };
+
+    if let Some(dir) = &prog.workdir {
+        cmd.current_dir(dir);
+    }
+    if prog.debug {
+        // leak checking needs ptrace (which the sandbox doesn't allow) & leaks don't matter here
+        cmd.env("ASAN_OPTIONS", "detect_leaks=0");
+        cmd.env("UBSAN_OPTIONS", "print_stacktrace=1");
+    }
+    Ok(cmd)
+}
 
-            cmd = Command::new(format!("./{}", name));
+/// finds where a program gets built (in the cache if there's one) & sorts out java's quirks
+fn locate(
+    prog: &Program, lang: &Lang, stem: &str, flags: &[OsString], std: &str, opt: &str
+) -> Result<(Built, Option<Build>), ExecError> {
+    let code = prog.code.as_path();
+    let build = match (&lang.compile, prog.cache) {
+        (Some(_), Some(cache)) => {
+            let source = std::fs::read(code).expect("couldn't read the code file");
+            let flags: Vec<u8> = flags.iter()
+                .flat_map(|o| [o.as_encoded_bytes(), b"\0"].concat())
+                .collect();
+            let defines: Vec<u8> = prog.defines.iter()
+                .flat_map(|o| [o.as_encoded_bytes(), b"\0"].concat())
+                .collect();
+            // the stem's in there since that's what the compiled program gets named
+            let parts: [&[u8]; 7] = [
+                &source, stem.as_bytes(), &lang.compiler_id()?, &flags,
+                std.as_bytes(), opt.as_bytes(), &defines
+            ];
+            let build = cache.build(&parts).expect("couldn't make the build directory");
+            Some(build)
+        }
+        _ => None
+    };
+    let dir = match &build {
+        Some(b) => b.dir.clone(),
+        None => {
+            std::fs::create_dir_all(&prog.build_dir).expect("couldn't make the build directory");
+            prog.build_dir.clone()
         }
     };
 
-    let mut cmd = cmd
-        .stdin(Stdio::piped())
+    let mut renamed = None;
+    let mut class = stem.to_string();
+    if lang.detect_class {
+        // javac gets to complain about the encoding, not us
+        let source = std::fs::read(code).expect("couldn't read the code file");
+        let classes = java_classes(&String::from_utf8_lossy(&source));
+        if let Some(main) = classes.main {
+            class = main;
+        }
+        // javac refuses to compile a public class that isn't in a file with the same name
+        if let Some(public) = classes.public.filter(|p| p != stem) {
+            let src_dir = dir.join("src");
+            std::fs::create_dir_all(&src_dir).expect("couldn't make the source directory");
+            let file = src_dir.join(format!("{}.{}", public, path_ext(code).unwrap()));
+            std::fs::copy(code, &file).expect("couldn't copy the code file");
+            renamed = Some(file);
+        }
+    }
+    Ok((Built { dir, renamed, class }, build))
+}
+
+fn spawn(mut cmd: Command, prog: &Program) -> Result<Child, ExecError> {
+    #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    if prog.sandbox {
+        crate::sandbox::sandbox(&mut cmd);
+    }
+    let res = cmd.stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .spawn()
-        .expect("something terribly wrong has happened");
+        .spawn();
+    let program = PathBuf::from(cmd.get_program());
+    match res {
+        Ok(child) => Ok(child),
+        // e.g. a command whose program got deleted, or an interpreter that isn't there anymore
+        Err(e) if e.kind() == ErrorKind::NotFound => Err(ExecError::path_not_found(program)),
+        Err(e) => Err(ExecError::spawn(program, &e))
+    }
+}
+
+/// waits for a running program & figures out how it did
+fn finish(
+    child: &mut Child, start: Instant, limits: &Limits, too_long: &AtomicBool,
+    stdout: JoinHandle<Vec<u8>>, stderr: JoinHandle<Vec<u8>>, sandboxed: bool
+) -> Result<ProgRes, ExecError> {
+    let (status, Usage { memory, cpu }) = wait_child(child, start, limits, too_long)?;
+    let time = start.elapsed();
+    if let (Some(tl), Clock::Cpu, Some(cpu)) = (limits.time, limits.clock, cpu) {
+        if cpu > tl {
+            return Err(ExecError::time_limit(tl, Clock::Cpu));
+        }
+    }
+    if let Some(ol) = limits.output {
+        if too_long.load(Ordering::Relaxed) {
+            return Err(ExecError::output_limit(ol));
+        }
+    }
+    let stdout = stdout.join().unwrap();
+    let stderr = stderr.join().unwrap();
+    if let (Some(ml), Some(used)) = (limits.memory, memory) {
+        let died_of_it = !status.success() && (out_of_memory(&stderr) || out_of_memory(&stdout));
+        if used > ml * 1024 || died_of_it {
+            return Err(ExecError::memory_limit(ml));
+        }
+    }
+    let exit = Exit::from(status);
+    // the sandbox's syscall filter kills things with this (outside of it, it's just a crash)
+    #[cfg(unix)]
+    if sandboxed && exit == Exit::Signal(libc::SIGSYS) {
+        return Err(ExecError::security(&escape(&stderr)));
+    }
+    if !status.success() {
+        return Err(ExecError::runtime_error(&escape(&stderr), exit));
+    }
+    Ok(ProgRes { stdout, stderr, time: time.as_secs_f64(), cpu, memory })
+}
+
+/// what a program used up by the time it finished (`None`s if the platform can't tell us)
+struct Usage {
+    /// peak resident memory in kilobytes
+    memory: Option<u64>,
+    /// user + system cpu time in seconds
+    cpu: Option<f64>
+}
 
-    let start = std::time::Instant::now();
+/// waits for the child to finish (killing it if it goes over the time limit)
+/// and gets its exit status along with what it used up
+#[cfg(unix)]
+fn wait_child(
+    child: &mut Child, start: Instant, limits: &Limits, too_long: &AtomicBool
+) -> Result<(ExitStatus, Usage), ExecError> {
+    use std::os::unix::process::ExitStatusExt;
 
-    let mut writer = std::io::BufWriter::new(cmd.stdin.take().unwrap());
-    // https://stackoverflow.com/questions/21615188
-    let mut lines = "".to_string();
-    for l in input.lines() {
-        let eol = '\n';
-        lines.push_str(l);
-        lines.push(eol);
+    let pid = child.id() as libc::pid_t;
+    loop {
+        let mut status = 0;
+        // SAFETY: rusage is plain old data, so all zeroes is a valid value
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        let ret = unsafe { libc::wait4(pid, &mut status, libc::WNOHANG, &mut usage) };
+        if ret == pid {
+            // linux gives this in kilobytes, mac gives it in bytes for some reason
+            let rss = usage.ru_maxrss as u64;
+            let rss = if cfg!(target_os = "macos") { rss / 1024 } else { rss };
+            let secs = |t: libc::timeval| t.tv_sec as f64 + t.tv_usec as f64 / 1e6;
+            let cpu = secs(usage.ru_utime) + secs(usage.ru_stime);
+            return Ok((ExitStatus::from_raw(status), Usage { memory: Some(rss), cpu: Some(cpu) }));
+        }
+        if ret < 0 {
+            panic!("couldn't wait for child: {}", std::io::Error::last_os_error());
+        }
+        check_limits(child, start, limits, too_long)?;
+        std::thread::sleep(Duration::from_millis(1));
     }
-    writer.write_all(lines.as_bytes()).expect("INPUT OH NO");
+}
 
-    writer.flush().expect("god i'm so tired");
-    let output = cmd.wait_with_output().expect("bruh...");
-    let time = start.elapsed();
-    let stdout = String::from_utf8(output.stdout).unwrap();
-    let stderr = String::from_utf8(output.stderr).unwrap();
-    if !output.status.success() {
-        return Err(ExecError::runtime_error(&stderr));
+#[cfg(not(unix))]
+fn wait_child(
+    child: &mut Child, start: Instant, limits: &Limits, too_long: &AtomicBool
+) -> Result<(ExitStatus, Usage), ExecError> {
+    loop {
+        if let Some(s) = child.try_wait().expect("bruh...") {
+            return Ok((s, Usage { memory: None, cpu: None }));
+        }
+        check_limits(child, start, limits, too_long)?;
+        std::thread::sleep(Duration::from_millis(1));
     }
-    Ok(ProgRes { stdout, stderr, time: time.as_secs_f64() })
 }
 
-fn cmd_exists(cmd: &str) -> bool {
-    match Command::new(cmd)
-        .arg("--version")
-        .stdout(Stdio::piped()).spawn() {
-        Ok(_) => true,
-        Err(e) => e.kind() != ErrorKind::NotFound
+fn check_limits(
+    child: &mut Child, start: Instant, limits: &Limits, too_long: &AtomicBool
+) -> Result<(), ExecError> {
+    let mut err = None;
+    if let Some(tl) = limits.time {
+        let wall = start.elapsed().as_secs_f64();
+        let over = match limits.clock {
+            Clock::Wall => wall > tl,
+            // if we can't tell how much cpu it's used so far it gets checked once it's done,
+            // and something that just sits around (e.g. waiting for input) gets killed eventually
+            Clock::Cpu => cpu_time(child.id()).is_some_and(|c| c > tl) || wall > 2.0 * tl + 1.0
+        };
+        if over {
+            err = Some(ExecError::time_limit(tl, limits.clock));
+        }
+    }
+    if let Some(ol) = limits.output {
+        if too_long.load(Ordering::Relaxed) {
+            err = Some(ExecError::output_limit(ol));
+        }
     }
+    // capping the address space instead would be easier, but the JVM & node reserve
+    // gigabytes of it up front & can't even start with a normal limit
+    if let Some(ml) = limits.memory {
+        if resident_memory(child.id()).is_some_and(|m| m > ml * 1024) {
+            err = Some(ExecError::memory_limit(ml));
+        }
+    }
+    if let Some(err) = err {
+        // the reader threads die on their own once the pipes close
+        child.kill().ok();
+        child.wait().ok();
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// how much cpu time a running process has used so far
+#[cfg(target_os = "linux")]
+fn cpu_time(pid: u32) -> Option<f64> {
+    let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    // the name's in parentheses & could have spaces in it, so skip past it
+    let fields: Vec<&str> = stat.get(stat.rfind(')')? + 2..)?.split(' ').collect();
+    // utime & stime (fields 14 & 15, counting from the pid)
+    let ticks: u64 = fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+    // SAFETY: sysconf doesn't touch any memory of ours
+    let per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    Some(ticks as f64 / per_sec as f64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_time(_pid: u32) -> Option<f64> {
+    None
+}
+
+/// how much memory a running process is using right now, in kilobytes
+#[cfg(target_os = "linux")]
+fn resident_memory(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmRSS:"))?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// everywhere else it only gets checked once the program's done
+#[cfg(not(target_os = "linux"))]
+fn resident_memory(_pid: u32) -> Option<u64> {
+    None
+}
+
+/// sometimes a program's allocations fail before it gets caught going over the limit,
+/// so we have to guess from how it died (the JVM says so on stdout, of all places)
+fn out_of_memory(output: &[u8]) -> bool {
+    let output = String::from_utf8_lossy(output);
+    ["bad_alloc", "MemoryError", "OutOfMemoryError", "out of memory"]
+        .iter().any(|m| output.contains(m))
+}
+
+/// writes the input to a pipe in another thread, closing it at the end so the program sees EOF
+/// (it's fine if the program quits before reading all of it)
+fn feed(
+    mut pipe: impl Write + Send + 'static, input: Vec<u8>
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        match pipe.write_all(&input).and_then(|_| pipe.flush()) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {}
+            Err(e) => panic!("couldn't write program input: {}", e)
+        }
+    })
+}
+
+/// reads a pipe to the end in another thread, giving up once it's over `limit` megabytes
+/// (everything read gets passed to `tee` too)
+fn drain(
+    mut pipe: impl Read + Send + 'static,
+    limit: Option<u64>, too_long: Arc<AtomicBool>,
+    mut tee: impl FnMut(&[u8]) + Send + 'static
+) -> JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let limit = limit.map(|l| (l * 1024 * 1024) as usize);
+        let mut buf = Vec::new();
+        let mut chunk = [0; 8192];
+        loop {
+            let n = match pipe.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => panic!("couldn't read program output: {}", e)
+            };
+            tee(&chunk[..n]);
+            buf.extend_from_slice(&chunk[..n]);
+            if limit.is_some_and(|l| buf.len() > l) {
+                too_long.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+        buf
+    })
 }
 
 // general utility methods
-pub(crate) fn path_ext(path: &PathBuf) -> Option<&str> {
+pub(crate) fn path_ext(path: &Path) -> Option<&str> {
     path.extension().and_then(std::ffi::OsStr::to_str)
 }
 
-pub(crate) fn path_str(path: &PathBuf) -> String {
-    path.clone().into_os_string().into_string().unwrap()
+pub(crate) fn path_str(path: &Path) -> String {
+    path.to_path_buf().into_os_string().into_string().unwrap()
+}
+
+/// splits bytes into lines like `str::lines` does (`\n` or `\r\n`, no empty line at the end)
+pub(crate) fn lines_of(bytes: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let empty = bytes.is_empty();
+    let bytes = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    bytes.split(|&b| b == b'\n')
+        .map(|l| l.strip_suffix(b"\r").unwrap_or(l))
+        .filter(move |_| !empty)
+}
+
+/// makes bytes printable- valid utf-8 is left alone, everything else turns into `\xNN`
+pub(crate) fn escape(bytes: &[u8]) -> String {
+    let mut res = String::new();
+    for chunk in bytes.utf8_chunks() {
+        res.push_str(chunk.valid());
+        for b in chunk.invalid() {
+            res.push_str(&format!("\\x{:02x}", b));
+        }
+    }
+    res
 }
 
-pub(crate) fn check_content(file: &PathBuf) -> Result<String, PathNotFound> {
+pub(crate) fn check_content(file: &PathBuf) -> Result<Vec<u8>, PathNotFound> {
     if file.is_file() {
-        return Ok(std::fs::read_to_string(file).unwrap());
+        return Ok(std::fs::read(file).unwrap());
     }
     Err(PathNotFound { path: file.clone() })
 }
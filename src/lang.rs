@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, OnceLock};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::errors::{ArgError, ExecError};
+use crate::exec::{can_run, path_ext};
+
+const DEFAULT_LANGS: &str = include_str!("languages.toml");
+/// how much of a file gets checked for a `#!` line
+const SHEBANG_MAX: u64 = 256;
+
+/// a language definition, see `languages.toml` for what each field does
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct Lang {
+    #[serde(skip)]
+    pub(crate) name: String,
+    pub(crate) ext: Vec<String>,
+    #[serde(default)]
+    pub(crate) cmds: Vec<String>,
+    pub(crate) compile: Option<String>,
+    pub(crate) run: String,
+    #[serde(default)]
+    pub(crate) version: Vec<String>,
+    #[serde(default)]
+    pub(crate) detect_class: bool,
+    /// the default for `{std}`
+    pub(crate) std: Option<String>,
+    /// the default for `{opt}`
+    pub(crate) opt: Option<String>,
+    /// extra flags for rebuilding a program to find out why it failed
+    pub(crate) debug_flags: Option<String>,
+    /// what `{cmd}` resolved to (`None` if the language isn't installed),
+    /// shared between copies so it only gets probed once
+    #[serde(skip)]
+    toolchain: Arc<OnceLock<Option<Toolchain>>>
+}
+
+#[derive(Debug, Clone)]
+struct Toolchain {
+    cmd: String,
+    /// everything the version probes printed
+    version: Vec<u8>
+}
+
+/// what the placeholders in a `Lang`'s commands get replaced with
+pub(crate) struct Vars<'a> {
+    pub(crate) file: &'a str,
+    pub(crate) stem: &'a str,
+    pub(crate) dir: &'a str,
+    pub(crate) out: &'a str,
+    pub(crate) class: &'a str,
+    pub(crate) std: &'a str,
+    pub(crate) opt: &'a str,
+    pub(crate) defines: &'a [OsString],
+    pub(crate) flags: &'a [OsString],
+    pub(crate) jvm: &'a [OsString],
+    pub(crate) args: &'a [OsString]
+}
+
+impl Lang {
+    /// makes sure the toolchain's installed & figures out the `{cmd}` to use
+    fn toolchain(&self) -> Result<&Toolchain, ExecError> {
+        let tc = self.toolchain.get_or_init(|| {
+            let cands = if self.cmds.is_empty() { vec![String::new()] } else { self.cmds.clone() };
+            cands.into_iter().find_map(|c| {
+                let mut version = Vec::new();
+                for v in &self.version {
+                    version.extend(probe(v, &c, false)?);
+                }
+                Some(Toolchain { cmd: c, version })
+            })
+        });
+        tc.as_ref().ok_or_else(|| ExecError::lang_not_found(&self.name))
+    }
+
+    /// the command to compile a program with (`None` if there's no need to)
+    pub(crate) fn compile_cmd(&self, vars: &Vars) -> Result<Option<Command>, ExecError> {
+        match &self.compile {
+            None => Ok(None),
+            Some(c) => Ok(Some(self.fill(c, vars)?))
+        }
+    }
+
+    pub(crate) fn run_cmd(&self, vars: &Vars) -> Result<Command, ExecError> {
+        self.fill(&self.run, vars)
+    }
+
+    fn fill(&self, template: &str, vars: &Vars) -> Result<Command, ExecError> {
+        fill(template, &self.toolchain()?.cmd, vars).ok_or_else(|| {
+            let err = std::io::Error::other("its command came out empty");
+            ExecError::spawn(PathBuf::from(&self.name), &err)
+        })
+    }
+
+    /// whether the compiler is `{cmd}`, i.e. whether `--compiler` can change it
+    pub(crate) fn picks_compiler(&self) -> bool {
+        self.compile.as_deref().is_some_and(|c| c.split_whitespace().next() == Some("{cmd}"))
+    }
+
+    /// identifies the compiler & how it's called, for telling builds apart
+    pub(crate) fn compiler_id(&self) -> Result<Vec<u8>, ExecError> {
+        let tc = self.toolchain()?;
+        let mut id = Vec::new();
+        let compile = self.compile.as_deref().unwrap_or("");
+        for part in [self.name.as_bytes(), compile.as_bytes(), tc.cmd.as_bytes()] {
+            id.extend(part);
+            id.push(0);
+        }
+        id.extend(&tc.version);
+        Ok(id)
+    }
+}
+
+/// all the languages aaargh can run
+#[derive(Debug, Clone)]
+pub(crate) struct Registry {
+    langs: Vec<Lang>,
+    /// the language from a program's `#!` line (see `read_shebang`)
+    script: Option<Lang>
+}
+
+impl Registry {
+    /// loads the built-in languages along with the ones from the user's config
+    /// (`path` if it's given, otherwise the default config location if it exists)
+    pub(crate) fn load(path: Option<&Path>) -> Result<Registry, ArgError> {
+        let mut langs = parse(DEFAULT_LANGS, "the default languages")?;
+        let path = match path {
+            Some(p) => Some(p.to_path_buf()),
+            None => default_path().filter(|p| p.is_file())
+        };
+        if let Some(p) = path {
+            let content = std::fs::read_to_string(&p).map_err(|e| ArgError {
+                err: format!("couldn't read {}: {}", p.display(), e)
+            })?;
+            // the user's languages get checked first
+            let mut user = parse(&content, &p.display().to_string())?;
+            langs.retain(|l| !user.iter().any(|u| u.name == l.name));
+            user.append(&mut langs);
+            langs = user;
+        }
+        Ok(Registry { langs, script: None })
+    }
+
+    /// makes a language use a specific `{cmd}`, making sure it actually works
+    pub(crate) fn set_cmd(&mut self, name: &str, cmd: &str) -> Result<(), ExecError> {
+        let lang = self.langs.iter_mut().find(|l| l.name == name).ok_or_else(
+            || ExecError::lang_not_found(name)
+        )?;
+        lang.cmds = vec![cmd.to_string()];
+        // something that was picked on purpose has to actually work, not just be there
+        let mut version = Vec::new();
+        for v in &lang.version {
+            let out = probe(v, cmd, true)
+                .ok_or_else(|| ExecError::lang_not_found(&format!("{} ({})", name, cmd)))?;
+            version.extend(out);
+        }
+        let tc = Toolchain { cmd: cmd.to_string(), version };
+        lang.toolchain = Arc::new(OnceLock::from(Some(tc)));
+        Ok(())
+    }
+
+    /// reads the `#!` line of a program's file if its extension doesn't match anything
+    /// (every program gets its own copy of the registry, so this only has to happen once)
+    pub(crate) fn read_shebang(&mut self, file: &Path) {
+        if self.ext_lang(file).is_none() {
+            self.script = shebang(file);
+        }
+    }
+
+    /// figures out what language a file's in from its extension,
+    /// or from its `#!` line if the extension doesn't match anything (see `read_shebang`)
+    pub(crate) fn file_lang(&self, file: &Path) -> Option<&Lang> {
+        self.ext_lang(file).or(self.script.as_ref())
+    }
+
+    fn ext_lang(&self, file: &Path) -> Option<&Lang> {
+        let ext = path_ext(file)?;
+        self.langs.iter().find(|l| l.ext.iter().any(|e| e == ext))
+    }
+}
+
+/// makes a language out of a script's `#!` line, e.g. `#!/usr/bin/env ruby`
+fn shebang(file: &Path) -> Option<Lang> {
+    let mut line = Vec::new();
+    let f = std::fs::File::open(file).ok()?;
+    // the kernel doesn't look any further than this either
+    BufReader::new(f.take(SHEBANG_MAX)).read_until(b'\n', &mut line).ok()?;
+    let interp = std::str::from_utf8(line.strip_prefix(b"#!")?).ok()?.trim();
+    let mut words = interp.split_whitespace();
+    let mut prog = words.next()?;
+    // `env` is always there, it's whatever it's running that might not be
+    if Path::new(prog).file_name().is_some_and(|n| n == "env") {
+        prog = words.find(|w| !w.starts_with('-')).unwrap_or(prog);
+    }
+    // running the interpreter to see if it's there could actually do something
+    // (think `#!/usr/bin/make -f`), so just look for it instead
+    let toolchain = can_run(prog).then(|| Toolchain { cmd: String::new(), version: Vec::new() });
+    Some(Lang {
+        name: format!("#!{}", interp),
+        ext: Vec::new(),
+        cmds: Vec::new(),
+        compile: None,
+        run: format!("{} {{file}} {{args}}", interp),
+        version: Vec::new(),
+        detect_class: false,
+        std: None,
+        opt: None,
+        debug_flags: None,
+        toolchain: Arc::new(OnceLock::from(toolchain))
+    })
+}
+
+fn default_path() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("aargh").join("languages.toml"))
+}
+
+fn parse(content: &str, src: &str) -> Result<Vec<Lang>, ArgError> {
+    let table: BTreeMap<String, Lang> = toml::from_str(content).map_err(|e| ArgError {
+        err: format!("couldn't parse {}: {}", src, e)
+    })?;
+    let mut langs = Vec::new();
+    for (name, mut l) in table {
+        let empty = |c: &str| c.split_whitespace().next().is_none();
+        if empty(&l.run) || l.compile.as_deref().is_some_and(empty) {
+            return Err(ArgError { err: format!("{} in {} has an empty command", name, src) });
+        }
+        // the program to run can't be something that might turn out to be nothing
+        let vanishes = |c: &str| c.split_whitespace().next().is_some_and(|first| {
+            ["{flags}", "{jvm}", "{args}", "{defines}"].contains(&first)
+                || first.contains("{std}") || first.contains("{opt}")
+        });
+        if vanishes(&l.run) || l.compile.as_deref().is_some_and(vanishes) {
+            return Err(ArgError {
+                err: format!("{} in {} has a command that doesn't start with a program", name, src)
+            });
+        }
+        l.name = name;
+        langs.push(l);
+    }
+    Ok(langs)
+}
+
+/// turns a command template into an actual command (`None` if it ends up empty)
+fn fill(template: &str, cmd: &str, vars: &Vars) -> Option<Command> {
+    let mut args = Vec::new();
+    for tok in template.split_whitespace() {
+        if tok == "{flags}" {
+            args.extend(vars.flags.iter().cloned());
+            continue;
+        }
+        if tok == "{jvm}" {
+            args.extend(vars.jvm.iter().cloned());
+            continue;
+        }
+        if tok == "{args}" {
+            args.extend(vars.args.iter().cloned());
+            continue;
+        }
+        if tok == "{defines}" {
+            args.extend(vars.defines.iter().map(|d| {
+                let mut def = OsString::from("-D");
+                def.push(d);
+                def
+            }));
+            continue;
+        }
+        // something like `-std={std}` has to go if there's no standard to put in
+        if (tok.contains("{std}") && vars.std.is_empty())
+            || (tok.contains("{opt}") && vars.opt.is_empty()) {
+            continue;
+        }
+        let tok = tok
+            .replace("{cmd}", cmd)
+            .replace("{file}", vars.file)
+            .replace("{stem}", vars.stem)
+            .replace("{dir}", vars.dir)
+            .replace("{out}", vars.out)
+            .replace("{class}", vars.class)
+            .replace("{std}", vars.std)
+            .replace("{opt}", vars.opt);
+        args.push(OsString::from(tok));
+    }
+    let (prog, args) = args.split_first()?;
+    let mut res = Command::new(prog);
+    res.args(args);
+    Some(res)
+}
+
+/// runs a version probe, giving back what it printed (`None` if the command doesn't exist,
+/// or if it's `strict` & the command couldn't start or failed)
+fn probe(template: &str, cmd: &str, strict: bool) -> Option<Vec<u8>> {
+    let mut args = template.split_whitespace().map(|t| t.replace("{cmd}", cmd));
+    let prog = args.next()?;
+    match Command::new(prog)
+        .args(args)
+        .stdin(Stdio::null())
+        .output() {
+        Ok(o) if strict && !o.status.success() => None,
+        Ok(o) => Some([o.stdout, o.stderr].concat()),
+        // if it's there but something else went wrong, we might as well try to use it
+        Err(e) if !strict && e.kind() != ErrorKind::NotFound => Some(Vec::new()),
+        Err(_) => None
+    }
+}
+
+/// the classes in a java file that matter for compiling & running it
+#[derive(Debug, Default)]
+pub(crate) struct JavaClasses {
+    /// the class with `main` in it (along with its package)
+    pub(crate) main: Option<String>,
+    /// the top-level public class, which javac wants the file to be named after
+    pub(crate) public: Option<String>
+}
+
+/// finds the main & public classes in some java code
+/// (good enough for competitive programming, probably not for much else)
+pub(crate) fn java_classes(src: &str) -> JavaClasses {
+    // get rid of comments, strings & chars so braces and the like in them don't count
+    let junk = Regex::new(r#"(?s)//[^\n]*|/\*.*?\*/|"(?:\\.|[^"\\])*"|'(?:\\.|[^'\\])*'"#).unwrap();
+    let src = junk.replace_all(src, " ");
+
+    let package = Regex::new(r"\bpackage\s+([\w.]+)\s*;").unwrap()
+        .captures(&src)
+        .map(|c| format!("{}.", &c[1]))
+        .unwrap_or_default();
+    let tokens = Regex::new(concat!(
+        r"\b(public\s+)?(?:(?:abstract|final|static|strictfp)\s+)*",
+        r"(?:class|interface|enum|record)\s+(\w+)",
+        r"|\bvoid\s+main\s*\(|\{|\}"
+    )).unwrap();
+
+    let mut res = JavaClasses::default();
+    let mut depth = 0;
+    let mut top_level = None;
+    for t in tokens.captures_iter(&src) {
+        match &t[0] {
+            "{" => depth += 1,
+            "}" => depth -= 1,
+            _ if t.get(2).is_some() => {
+                if depth == 0 {
+                    let name = t[2].to_string();
+                    if t.get(1).is_some() && res.public.is_none() {
+                        res.public = Some(name.clone());
+                    }
+                    top_level = Some(name);
+                }
+            }
+            _ => {
+                if res.main.is_none() {
+                    res.main = top_level.as_ref().map(|c| format!("{}{}", package, c));
+                }
+            }
+        }
+    }
+    res
+}
@@ -1,65 +1,201 @@
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Mutex;
 
 use structopt::StructOpt;
 use anyhow::{Context, Error, Result};
 use colored::Colorize;
-
-use crate::exec::{check_content, exec, ProgRes};
-use crate::cli::{RunOptions, Cli};
+use regex::Regex;
+
+use crate::cache::Cache;
+use crate::exec::{
+    check_content, escape, exec, lines_of, Clock, Exit, Limits, ProgRes, Program, Speaker
+};
+use crate::cli::{for_prog, words_for_prog, Cli, Prog, RunOptions};
+use crate::diff::DiffOpts;
 use crate::errors::ExecError;
+use crate::lang::Registry;
+use crate::verdict::{Summary, Verdict};
 
+mod cache;
 mod cli;
 mod diff;
 mod exec;
 mod errors;
+mod lang;
+mod pool;
+#[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+mod sandbox;
+mod verdict;
 
 const FMT_TOKEN: &str = "{}";
 
-fn path_test(path: &PathBuf) -> Result<(), errors::PathNotFound> {
+fn path_test(path: &Path) -> Result<(), errors::PathNotFound> {
     if path.exists() {
         return Ok(());
     }
-    Err(errors::PathNotFound { path: path.clone() })
+    Err(errors::PathNotFound { path: path.to_path_buf() })
+}
+
+fn limits(args: &Cli, prog: Prog) -> Limits {
+    Limits {
+        time: for_prog(&args.time_limit, prog),
+        clock: args.clock,
+        memory: for_prog(&args.memory_limit, prog),
+        output: for_prog(&args.output_limit, prog)
+    }
+}
+
+/// turns a python interpreter from the command line into something runnable
+/// (directories are taken as virtualenvs)
+fn python_interp(interp: &str) -> String {
+    let path = Path::new(interp);
+    if !path.is_dir() {
+        return interp.to_string();
+    }
+    let bin = if cfg!(windows) {
+        path.join("Scripts").join("python.exe")
+    } else { path.join("bin").join("python") };
+    exec::path_str(&bin)
+}
+
+fn program<'a>(
+    args: &Cli, prog: Prog, code: &Path, options: &RunOptions,
+    langs: &Registry, cache: Option<&'a Cache>, scratch: &Path,
+) -> Result<Program<'a>, Error> {
+    let mut langs = langs.clone();
+    let command = exec::parse_command(code);
+    if command.is_none() {
+        langs.read_shebang(code);
+    }
+    if let Some(py) = for_prog(&args.python, prog) {
+        langs.set_cmd("python", &python_interp(&py))
+            .with_context(|| format!("bad python interpreter for the {}", prog.desc()))?;
+    }
+    if let Some(compiler) = for_prog(&args.compiler, prog) {
+        let lang = match langs.file_lang(code) {
+            Some(l) if command.is_none() && l.picks_compiler() => l.name.clone(),
+            _ => return Err(Error::msg(format!(
+                "can't pick a compiler for the {} ({})", prog.desc(), exec::path_str(code)
+            )))
+        };
+        langs.set_cmd(&lang, &compiler)
+            .with_context(|| format!("bad compiler for the {}", prog.desc()))?;
+    }
+
+    Ok(Program {
+        role: prog,
+        code: code.to_path_buf(),
+        command,
+        compile_flags: words_for_prog(&args.compile_flags, prog),
+        std: for_prog(&args.std, prog),
+        opt: for_prog(&args.opt, prog),
+        defines: words_for_prog(&args.defines, prog),
+        run_args: words_for_prog(&args.run_args, prog),
+        jvm_opts: words_for_prog(&args.jvm_opts, prog),
+        options: options.clone(),
+        limits: limits(args, prog),
+        langs,
+        cache,
+        build_dir: scratch.join(<&str>::from(prog)),
+        workdir: None,
+        sandbox: args.sandbox,
+        debug: false,
+        built: Default::default()
+    })
+}
+
+/// all the programs a test might need
+/// (each worker gets its own copy, so they can each run in their own directory)
+#[derive(Clone)]
+struct Progs<'a> {
+    code: Program<'a>,
+    gen: Option<Program<'a>>,
+    ans: Option<Program<'a>>,
+    checker: Option<Program<'a>>,
+    interactor: Option<Program<'a>>,
+    /// the code file built with sanitizers, for rerunning tests it fails
+    debug: Option<Program<'a>>
+}
+
+impl<'a> Progs<'a> {
+    /// copies of the programs that run in `dir` instead of the current directory
+    fn in_dir(&self, dir: &Path) -> Result<Progs<'a>, Error> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("couldn't make {}", exec::path_str(dir)))?;
+        let in_dir = |p: &Program<'a>| p.in_dir(dir);
+        Ok(Progs {
+            code: in_dir(&self.code)?,
+            gen: self.gen.as_ref().map(in_dir).transpose()?,
+            ans: self.ans.as_ref().map(in_dir).transpose()?,
+            checker: self.checker.as_ref().map(in_dir).transpose()?,
+            interactor: self.interactor.as_ref().map(in_dir).transpose()?,
+            debug: self.debug.as_ref().map(in_dir).transpose()?
+        })
+    }
 }
 
 fn get_output(
-    code: &PathBuf, input: &str,
-    options: &RunOptions, compiled: bool,
+    prog: &Program, input: &[u8], compiled: bool,
     fin: &Option<PathBuf>, fout: &Option<PathBuf>,
-) -> Result<(ProgRes, String), Error> {
+) -> Result<(ProgRes, Vec<u8>), Error> {
+    // programs that use files get a fresh directory every time they run,
+    // so they can't trip over each other's files or leftovers from an earlier test
+    let tmp = match (fin, fout) {
+        (None, None) => None,
+        _ => Some(match &prog.workdir {
+            Some(d) => tempfile::tempdir_in(d),
+            None => tempfile::tempdir()
+        }.with_context(|| "couldn't make a directory for the program's files")?)
+    };
+    let code = prog.code.clone();
+    let moved;
+    let prog = match &tmp {
+        Some(t) => {
+            moved = prog.in_dir(t.path())?;
+            &moved
+        }
+        None => prog
+    };
+    let in_workdir = |f: &PathBuf| match &prog.workdir {
+        Some(d) => d.join(f),
+        None => f.clone()
+    };
+    let fin = fin.as_ref().map(in_workdir);
+    let fout = fout.as_ref().map(in_workdir);
     match fin {
         None => {}
         Some(dir) => {
             let mut input_file = std::fs::File::create(dir)
                 .expect("input file creation failed");
-            for l in input.lines() {
-                let eol = '\n';
-                let mut l = l.to_string();
-                l.push(eol);
-                input_file.write_all(l.as_bytes())
+            for l in lines_of(input) {
+                input_file.write_all(&[l, b"\n"].concat())
                     .expect("writing to input file failed");
             }
         }
     }
 
-    let res = exec::exec(code, input, &options, compiled)
+    let res = exec::exec(prog, input, compiled)
         .with_context(|| format!(
-            "error when executing {}", exec::path_str(code)
+            "error when executing {}", exec::path_str(&code)
         ));
 
-    return match res {
+    match res {
         Ok(res) => {
             match fout {
-                None => Ok((res, "".to_string())),
+                None => Ok((res, Vec::new())),
                 Some(f) => {
-                    let file = std::fs::read_to_string(f).expect("crap");
+                    let file = std::fs::read(&f).with_context(|| format!(
+                        "{} didn't write its output to {}",
+                        exec::path_str(&code), exec::path_str(f.file_name().unwrap().as_ref())
+                    ))?;
                     Ok((res, file))
                 }
             }
         }
         Err(err) => Err(err)
-    };
+    }
 }
 
 fn prog_res(
@@ -68,55 +204,261 @@ fn prog_res(
     mut out: impl Write,
 ) {
     if stderr {
-        writeln!(out, "stderr output:\n{}", res.stderr.blue()).expect("oh no");
+        writeln!(out, "stderr output:\n{}", escape(&res.stderr).blue()).expect("oh no");
     }
     if stdout {
-        writeln!(out, "stdout output:\n{}", res.stdout.green()).expect("oh no");
+        writeln!(out, "stdout output:\n{}", escape(&res.stdout).green()).expect("oh no");
+    }
+}
+
+/// runs the code file on a single test case, prints how it went & gives its verdict
+fn run_test(
+    args: &Cli, progs: &Progs,
+    input: &[u8], ans: &Option<Vec<u8>>,
+    compiled: bool, writer: &mut DumbWriter,
+) -> Result<Verdict, Error> {
+    let (code, checker) = (&progs.code, &progs.checker);
+    let res = get_output(
+        code, input, compiled,
+        &args.prog_fin, &args.prog_fout,
+    );
+    let (normal, file) = match res {
+        Ok(r) => r,
+        Err(e) => {
+            let verdict = error_verdict(e, writer)?;
+            debug_rerun(args, &progs.debug, input, verdict, writer)?;
+            return Ok(verdict);
+        }
+    };
+
+    prog_res(&normal, args.prog_stdout, args.prog_stderr, &mut writer.out);
+    stats(&normal, writer);
+
+    let output = match args.prog_fout {
+        None => normal.stdout,
+        Some(_) => file
+    };
+    let diff_opts = DiffOpts {
+        whitespace_matters: args.whitespace_matters,
+        str_case: args.str_case,
+        one_abort: args.one_abort
+    };
+    let verdict = validate(
+        &output, ans, checker,
+        compiled, diff_opts,
+        &mut writer.out,
+    ).with_context(|| "checking error")?;
+
+    if verdict.is_ok() {
+        writer.dumb_write(&"hooray, test case correct!".bright_green());
+    } else {
+        writer.write(&format!("verdict: {}", verdict).red());
+        debug_rerun(args, &progs.debug, input, verdict, writer)?;
+    }
+    Ok(verdict)
+}
+
+/// reruns a WA or RE with the code file built with sanitizers & the like
+/// (if its language has `debug_flags`) and prints whatever they caught
+fn debug_rerun(
+    args: &Cli, debug: &Option<Program>, input: &[u8],
+    verdict: Verdict, writer: &mut DumbWriter,
+) -> Result<(), Error> {
+    if !matches!(verdict, Verdict::WrongAnswer | Verdict::RuntimeError) {
+        return Ok(());
+    }
+    let debug = match debug {
+        Some(d) => d,
+        None => return Ok(())
+    };
+    // the debug build only gets compiled when it's needed, so workers have to take turns
+    static RERUN: Mutex<()> = Mutex::new(());
+    let _turn = RERUN.lock().unwrap_or_else(|e| e.into_inner());
+    // every worker's copy knows once one of them has built it
+    let compiled = debug.built.get().is_some();
+
+    writer.write(&"rerunning with sanitizers...".cyan());
+    let stderr = match get_output(debug, input, compiled, &args.prog_fin, &args.prog_fout) {
+        Ok((res, _)) => escape(&res.stderr),
+        Err(e) => match e.downcast_ref::<ExecError>() {
+            Some(ExecError::RuntimeError(re)) => re.err.clone(),
+            Some(ExecError::CompileError(ce)) => {
+                writer.write(&format!("the sanitized build didn't compile:\n{}", ce).red());
+                return Ok(());
+            }
+            Some(err) => {
+                writer.write(&format!("the sanitized build didn't finish: {}", err).red());
+                return Ok(());
+            }
+            None => return Err(e)
+        }
+    };
+    match sanitizer_report(&stderr) {
+        Some(report) => writer.write(&format!("sanitizer report:\n{}", report).red()),
+        None => writer.write(&"the sanitizers didn't find anything".cyan())
+    }
+    Ok(())
+}
+
+/// picks out what the sanitizers (or the debug STL) said from the rest of a program's stderr
+fn sanitizer_report(stderr: &str) -> Option<String> {
+    let start = Regex::new(r"^==\d+==ERROR: |: runtime error: |/debug/[^:]*:\d+:$").unwrap();
+    let lines: Vec<&str> = stderr.lines().collect();
+    let first = lines.iter().position(|l| start.is_match(l))?;
+    // everything after asan's summary is just a dump of its shadow memory
+    let last = lines[first..].iter()
+        .position(|l| l.starts_with("SUMMARY: "))
+        .map_or(lines.len(), |i| first + i + 1);
+    Some(lines[first..last].join("\n"))
+}
+
+/// runs the code file against the interactor on a single test case,
+/// prints how it went (along with the whole conversation if it failed) & gives its verdict
+fn run_interactive(
+    args: &Cli, code: &Program, interactor: &Program,
+    input: &[u8], compiled: bool, writer: &mut DumbWriter,
+) -> Result<Verdict, Error> {
+    let res = exec::interact(code, interactor, input, compiled)
+        .with_context(|| format!(
+            "error when executing {}", exec::path_str(&code.code)
+        ));
+    let res = match res {
+        Ok(r) => r,
+        Err(e) => return error_verdict(e, writer)
+    };
+
+    // 0 is AC, 1 is WA & 2 is PE, just like testlib
+    let (int_verdict, msg) = match res.interactor {
+        Ok(r) => (Verdict::Accepted, escape(&r.stderr)),
+        Err(ExecError::RuntimeError(re)) if re.exit == Exit::Code(1) =>
+            (Verdict::WrongAnswer, re.err),
+        Err(ExecError::RuntimeError(re)) if re.exit == Exit::Code(2) =>
+            (Verdict::PresentationError, re.err),
+        Err(e) => return Err(Error::new(e).context("the interactor failed"))
+    };
+    let sol_verdict = match &res.sol {
+        Ok(r) => {
+            prog_res(r, false, args.prog_stderr, &mut writer.out);
+            stats(r, writer);
+            Verdict::Accepted
+        }
+        Err(e) => {
+            print_error(e, writer);
+            Verdict::from_error(e).unwrap_or(Verdict::RuntimeError)
+        }
+    };
+    // the solution going over a limit (or getting caught by the sandbox) beats whatever
+    // the interactor thinks, but if it crashed it's probably because the interactor hung up on it
+    let over_limit = matches!(
+        sol_verdict,
+        Verdict::TimeLimit | Verdict::MemoryLimit
+            | Verdict::OutputLimit | Verdict::SecurityViolation
+    );
+    let verdict = if over_limit || int_verdict.is_ok() { sol_verdict } else { int_verdict };
+
+    if !verdict.is_ok() && !msg.trim().is_empty() {
+        writer.write(&format!("interactor message:\n{}", msg).red());
+    }
+    if !verdict.is_ok() || args.prog_stdout {
+        writer.write(&"transcript (> is the solution, < is the interactor):".cyan());
+        for (speaker, said) in &res.transcript {
+            for l in lines_of(said) {
+                match speaker {
+                    Speaker::Solution => writer.write(&format!("> {}", escape(l)).green()),
+                    Speaker::Interactor => writer.write(&format!("< {}", escape(l)).blue())
+                }
+            }
+        }
+    }
+    if verdict.is_ok() {
+        writer.dumb_write(&"hooray, test case correct!".bright_green());
+    } else {
+        writer.write(&format!("verdict: {}", verdict).red());
+    }
+    Ok(verdict)
+}
+
+/// prints how an error went if it's the program's fault and gives its verdict
+/// (everything else just gets passed on)
+fn error_verdict(e: Error, writer: &mut DumbWriter) -> Result<Verdict, Error> {
+    let err = match e.downcast_ref::<ExecError>() {
+        Some(err) => err,
+        None => return Err(e)
+    };
+    let verdict = match Verdict::from_error(err) {
+        Some(v) => v,
+        None => return Err(e)
+    };
+    print_error(err, writer);
+    writer.write(&format!("verdict: {}", verdict).red());
+    Ok(verdict)
+}
+
+fn print_error(err: &ExecError, writer: &mut DumbWriter) {
+    match err {
+        ExecError::TimeLimit(tle) => writer.write(&format!(
+            "{}: > {} s (time limit exceeded)",
+            if tle.clock == Clock::Cpu { "cpu time" } else { "exec time" }, tle.limit
+        ).red()),
+        ExecError::MemoryLimit(mle) => writer.write(&format!(
+            "memory: > {} MB (memory limit exceeded)", mle.limit
+        ).red()),
+        _ => writer.write(&err.to_string().red())
     }
 }
 
+fn stats(res: &ProgRes, writer: &mut DumbWriter) {
+    let mut stats = format!("exec time: {} s", res.time);
+    if let Some(cpu) = res.cpu {
+        stats.push_str(&format!(", cpu time: {:.3} s", cpu));
+    }
+    if let Some(kb) = res.memory {
+        stats.push_str(&format!(", memory: {:.1} MB", kb as f64 / 1024.0));
+    }
+    writer.dumb_write(&stats.cyan());
+}
+
 fn validate(
-    output: &String,
-    ans: &Option<String>,
-    checker: &Option<PathBuf>,
-    compiled: bool,
-    whitespace_matters: bool, str_case: bool, one_abort: bool,
+    output: &[u8],
+    ans: &Option<Vec<u8>>,
+    checker: &Option<Program>,
+    compiled: bool, opts: DiffOpts,
     mut out: impl Write,
-) -> Result<bool, ExecError> {
+) -> Result<Verdict, ExecError> {
     if let Some(a) = ans {
-        let diff_res = diff::diff_lines(
-            output.lines().into_iter(),
-            a.lines().into_iter(),
-            whitespace_matters, str_case, one_abort,
+        let diff_res = diff::diff_bytes(
+            output,
+            a,
+            opts,
             out,
         );
         return Ok(diff_res);
     }
     if let Some(c) = checker {
-        let correct = exec::exec(
-            &c, output,
-            &RunOptions::None, compiled,
-        );
+        let correct = exec::exec(c, output, compiled);
         return match correct {
             Ok(o) => {
-                if o.stdout.trim().to_lowercase() == "ok" {
-                    return Ok(true);
+                let msg = escape(&o.stdout);
+                if msg.trim().to_lowercase() == "ok" {
+                    return Ok(Verdict::Accepted);
                 }
                 writeln!(
                     out, "{}",
-                    format!("incorrect output- checker message:\n{}", o.stdout).red()
+                    format!("incorrect output- checker message:\n{}", msg).red()
                 ).expect("oh no");
-                Ok(false)
+                Ok(Verdict::WrongAnswer)
             }
             Err(e) => Err(e)
         };
     }
-    Ok(true)  // PISS OFF RUST, YOU MEMORY-SAFE PIECE OF GARBAGE
+    Ok(Verdict::Accepted)  // PISS OFF RUST, YOU MEMORY-SAFE PIECE OF GARBAGE
 }
 
+/// holds onto what a test prints until it's its turn to print it
+/// (otherwise tests running at the same time would get all mixed up)
 struct DumbWriter {
     silence: bool,
-    out: std::io::Stdout,
+    out: Vec<u8>,
 }
 
 impl DumbWriter {
@@ -129,123 +471,185 @@ impl DumbWriter {
     fn write(&mut self, s: &impl std::fmt::Display) {
         writeln!(self.out, "{}", s).expect("you're adopted, rust.");
     }
+
+    fn flush(&mut self) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&self.out).and_then(|_| stdout.flush()).expect("you're adopted, rust.");
+        self.out.clear();
+    }
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// runs the code on a single test case with whatever's judging it
+fn judge(
+    args: &Cli, progs: &Progs,
+    input: &[u8], ans: &Option<Vec<u8>>,
+    compiled: bool, writer: &mut DumbWriter,
+) -> Result<Verdict, Error> {
+    match &progs.interactor {
+        Some(i) => run_interactive(args, &progs.code, i, input, compiled, writer),
+        None => run_test(args, progs, input, ans, compiled, writer)
+    }
+}
+
+/// generates test case `t` and runs the code on it
+fn gen_test(args: &Cli, progs: &Progs, t: u32, writer: &mut DumbWriter) -> Result<Verdict, Error> {
+    let gen = progs.gen.as_ref().unwrap();
+    let tc = get_output(
+        gen, b"", t > 1,
+        &None, &None,
+    )?.0.stdout;  // discard stderr
+
+    let correct = match &progs.ans {
+        Some(ans) => {
+            let (res, file) = get_output(
+                ans, &tc, t > 1,
+                &args.prog_fin, &args.prog_fout,
+            )?;
+            // the brute solution writes its answer to the same file the code file does
+            Some(match args.prog_fout {
+                None => res.stdout,
+                Some(_) => file
+            })
+        }
+        None => None
+    };
+
+    writer.dumb_write(&format!("TEST CASE {}", t).cyan().bold());
+    let verdict = judge(args, progs, &tc, &correct, t > 1, writer)?;
+    if !verdict.is_ok() {
+        writer.write(&format!("{}\n{}", "test case failed:".red(), escape(&tc).red()));
+    }
+    Ok(verdict)
+}
+
+/// prints the verdict counts and turns them into an exit code
+fn report(summary: &Summary, writer: &mut DumbWriter) -> ExitCode {
+    writer.write(&format!("summary: {}", summary).cyan());
+    if summary.all_ok() {
+        writer.write(&"all correct! (could be good or bad, it depends.)".yellow());
+        return ExitCode::SUCCESS;
+    }
+    ExitCode::FAILURE
+}
+
+fn main() -> Result<ExitCode, Box<dyn std::error::Error>> {
     let args: Cli = Cli::from_args();
-    let run_options = args.run_options.unwrap_or_else(|| RunOptions::None);
+    let run_options = args.run_options.clone().unwrap_or(RunOptions::None);
 
-    path_test(&args.code)?;
+    if exec::parse_command(&args.code).is_none() {
+        path_test(&args.code)?;
+    }
+    if args.sandbox && !exec::CAN_SANDBOX {
+        return Err(Error::msg("the sandbox only works on linux (x86-64 or arm64)").into());
+    }
+    #[cfg(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64")))]
+    if args.sandbox {
+        sandbox::probe().map_err(|e| Error::msg(format!(
+            "couldn't set up the sandbox: {}\n\
+            (this system probably doesn't let normal users make user namespaces)", e
+        )))?;
+    }
+    let langs = Registry::load(args.lang_config.as_deref())
+        .with_context(|| "couldn't load the language definitions")?;
+    let cache = Cache::open();
+    if let (true, Some(c)) = (args.clear_cache, &cache) {
+        c.clear().with_context(|| "couldn't clear the compile cache")?;
+    }
+    let cache = if args.no_cache { None } else { cache.as_ref() };
+    // gets deleted once we're done
+    let scratch = tempfile::Builder::new().prefix("aargh").tempdir()
+        .with_context(|| "couldn't make a temporary directory")?;
+    let scratch = scratch.path();
+
+    let load = |prog, code: &Option<PathBuf>, options| code.as_ref().map(
+        |c| program(&args, prog, c, options, &langs, cache, scratch)
+    ).transpose();
+    let code = program(&args, Prog::Code, &args.code, &run_options, &langs, cache, scratch)?;
+    let progs = Progs {
+        debug: if args.no_sanitize { None } else { code.debug_build() },
+        code,
+        gen: load(Prog::Gen, &args.gen, &RunOptions::None)?,
+        ans: load(Prog::Ans, &args.ans, &run_options)?,
+        checker: load(Prog::Checker, &args.checker, &RunOptions::None)?,
+        interactor: load(Prog::Interactor, &args.interactor, &RunOptions::None)?
+    };
+    if progs.gen.is_some() && progs.ans.is_none() && progs.interactor.is_none() {
+        return Err(Error::msg(
+            "the generator needs an --ans to check against (or an --interactor)"
+        ).into());
+    }
 
-    let mut writer = DumbWriter { silence: args.silence, out: std::io::stdout() };
+    let jobs = match args.jobs {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        j => j
+    };
+    // with more than one job going, each one gets its own directory to run in
+    // (and sandboxed programs don't get to mess with the current one)
+    let workers = if jobs == 1 && !args.sandbox { vec![progs.clone()] } else {
+        (0..jobs).map(|j| progs.in_dir(&scratch.join(format!("job{}", j))))
+            .collect::<Result<Vec<_>, Error>>()?
+    };
+
+    let mut writer = DumbWriter { silence: args.silence, out: Vec::new() };
+    let mut summary = Summary::default();
+    let new_writer = || DumbWriter { silence: args.silence, out: Vec::new() };
     if args.gen.is_some() {
-        let gen_code = args.gen.unwrap();
         let default = if args.gen_forever { u32::MAX } else { 50 };
-        let mut found_bad = false;
-        for t in 1..=args.gen_amt.unwrap_or(default) {
-            let tc = get_output(
-                &gen_code, "",
-                &RunOptions::None, t > 1,
-                &None, &None,
-            )?.0.stdout;  // discard stderr
-
-            let correct = get_output(
-                &args.ans.as_ref().unwrap(), &tc,
-                &run_options, t > 1,
-                &args.prog_fin, &args.prog_fout,
-            )?.0.stdout;
-
-            writer.dumb_write(&format!("TEST CASE {}", t).cyan().bold());
-            let (normal, file) = get_output(
-                &args.code, &tc,
-                &run_options, t > 1,
-                &args.prog_fin, &args.prog_fout,
-            )?;
-            prog_res(&normal, args.prog_stdout, args.prog_stderr, &mut std::io::stdout());
-            writer.dumb_write(&format!("exec time: {} s", normal.time).cyan());
-
-            let ans = match args.prog_fout {
-                None => normal.stdout,
-                Some(_) => file
-            };
-            let diff_res = validate(
-                &ans, &Some(correct), &None,
-                t > 1,
-                args.whitespace_matters, args.str_case, args.one_abort,
-                &mut std::io::stdout(),
-            ).with_context(|| "checking error")?;
-            if diff_res {
-                println!("{}\n{}", "test case failed:".red(), tc.red());
-                found_bad = true;
-                break;
+        pool::run_tests(
+            jobs, args.gen_amt.unwrap_or(default),
+            |w, t| {
+                let mut writer = new_writer();
+                let verdict = gen_test(&args, &workers[w], t, &mut writer)?;
+                Ok(Some((verdict, writer)))
+            },
+            |_, res| {
+                let (verdict, mut test_writer) = res.unwrap();
+                test_writer.flush();
+                summary.add(verdict);
+                Ok(verdict.is_ok())
             }
-
-            writer.dumb_write(&"hooray, test case correct!".bright_green());
-        }
-        if !found_bad {
-            writer.write(&"all correct! (could be good or bad, it depends.)".yellow());
-        }
-        return Ok(());
+        )?;
+        let code = report(&summary, &mut writer);
+        writer.flush();
+        return Ok(code);
     }
 
-    let args_fin = args.fin.with_context(
-        || format!("input file or directory not found")
+    let args_fin = args.fin.clone().with_context(
+        || "input file or directory not found"
     ).unwrap();
 
     if args_fin.is_file() {
-        let (normal, file) = get_output(
-            &args.code, &check_content(&args_fin).unwrap(),
-            &run_options, false,
-            &args.prog_fin, &args.prog_fout,
+        let ans = if let Some(f) = &args.fout { Some(check_content(f)?) } else { None };
+        let verdict = judge(
+            &args, &workers[0],
+            &check_content(&args_fin)?, &ans,
+            false, &mut writer,
         )?;
+        writer.flush();
+        return Ok(if verdict.is_ok() { ExitCode::SUCCESS } else { ExitCode::FAILURE });
+    }
 
-        prog_res(&normal, args.prog_stdout, args.prog_stderr, &mut std::io::stdout());
-        writer.dumb_write(&format!("exec time: {} s", normal.time).cyan());
-
-        let ans = match args.prog_fout {
-            None => normal.stdout,
-            Some(_) => file
-        };
-        let diff_res = validate(
-            &ans,
-            &if let Some(f) = args.fout { Some(check_content(&f)?) } else { None },
-            &args.checker,
-            false, args.whitespace_matters, args.str_case, args.one_abort,
-            &mut std::io::stdout(),
-        ).with_context(|| "checking error")?;
-        if !diff_res {
-            writer.dumb_write(&"hooray, test case correct!".bright_green());
-        }
-    } else {
-        let default = "{}.in".to_string();
-        let fin_fmt = args.fin_fmt.as_ref().unwrap_or(&default);
-        let default = "{}.out".to_string();
-        let fout_fmt = args.fout_fmt.as_ref().unwrap_or(&default);
-
-        let once = fin_fmt.matches(FMT_TOKEN).count() == 0
-            && fout_fmt.matches(FMT_TOKEN).count() == 0;
-
-        let mut t = 1;
-        let mut found_bad = true;
-        loop {
-            let fin_name = fin_fmt.replace(FMT_TOKEN, &t.to_string());
+    let default = "{}.in".to_string();
+    let fin_fmt = args.fin_fmt.as_ref().unwrap_or(&default);
+    let default = "{}.out".to_string();
+    let fout_fmt = args.fout_fmt.as_ref().unwrap_or(&default);
+
+    let once = fin_fmt.matches(FMT_TOKEN).count() == 0
+        && fout_fmt.matches(FMT_TOKEN).count() == 0;
+    let fin_path = |t: u32| {
+        let mut fin = args_fin.clone();
+        fin.extend(&[fin_fmt.replace(FMT_TOKEN, &t.to_string())]);
+        fin
+    };
 
-            let mut fin = args_fin.clone();
-            fin.extend(&[fin_name]);
+    pool::run_tests(
+        jobs, if once { 1 } else { u32::MAX },
+        |w, t| {
+            let fin = fin_path(t);
             if !fin.is_file() {
-                eprintln!("{:?} doesn't exist, stopping testing loop", fin);
-                break;
+                return Ok(None);
             }
 
-            writer.dumb_write(&format!("TEST CASE {}", t).cyan().bold());
-            let (normal, file) = get_output(
-                &args.code, &check_content(&fin)?,
-                &run_options, t > 1,
-                &args.prog_fin, &args.prog_fout,
-            )?;
-            prog_res(&normal, args.prog_stdout, args.prog_stderr, &mut std::io::stdout());
-            writer.dumb_write(&format!("exec time: {} s", normal.time).cyan());
-
             let mut fout = None;
             if let Some(f) = &args.fout {
                 let fout_name = fout_fmt.replace(FMT_TOKEN, &t.to_string());
@@ -254,31 +658,31 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 fout = Some(check_content(&fout_path)?);
             }
 
-            let ans = match args.prog_fout {
-                None => normal.stdout,
-                Some(_) => file
+            let mut writer = new_writer();
+            writer.dumb_write(&format!("TEST CASE {}", t).cyan().bold());
+            let verdict = judge(
+                &args, &workers[w],
+                &check_content(&fin)?, &fout,
+                t > 1, &mut writer,
+            )?;
+            Ok(Some((verdict, writer)))
+        },
+        |t, res| {
+            let (verdict, mut test_writer) = match res {
+                Some(r) => r,
+                None => {
+                    eprintln!("{:?} doesn't exist, stopping testing loop", fin_path(t));
+                    return Ok(false);
+                }
             };
-            let correct = validate(
-                &ans, &fout, &args.checker,
-                t > 1,
-                args.whitespace_matters, args.str_case, args.one_abort,
-                &mut std::io::stdout(),
-            ).with_context(|| "checking error")?;
-            if correct {
-                writer.dumb_write(&"hooray, test case correct!".bright_green());
-            } else {
-                found_bad = true;
-            }
-
-            t += 1;
-            if once {
-                break;
-            }
-        }
-        if !found_bad {
-            writer.write(&"all correct! (could be good or bad, it depends.)".yellow());
+            test_writer.flush();
+            summary.add(verdict);
+            // no point in going on if there's nothing to run
+            Ok(verdict != Verdict::CompileError)
         }
-    }
+    )?;
 
-    Ok(())
+    let code = report(&summary, &mut writer);
+    writer.flush();
+    Ok(code)
 }
@@ -0,0 +1,71 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+
+use anyhow::Error;
+
+/// runs tests 1, 2, 3... (up to `amount`) on `jobs` threads & hands them to `report` in order
+/// ### arguments:
+/// * run: runs a test on a worker, giving `None` if there's no such test
+/// * report: gets each test's result in order, and says whether to keep going
+///
+/// the first test runs on its own so everything gets compiled exactly once, and the rest
+/// only start once it's done (tests that were already running when we stop just get thrown out)
+pub(crate) fn run_tests<T: Send>(
+    jobs: usize, amount: u32,
+    run: impl Fn(usize, u32) -> Result<Option<T>, Error> + Sync,
+    mut report: impl FnMut(u32, Option<T>) -> Result<bool, Error>,
+) -> Result<(), Error> {
+    if amount == 0 {
+        return Ok(());
+    }
+    let first = run(0, 1)?;
+    let exists = first.is_some();
+    if !report(1, first)? || !exists {
+        return Ok(());
+    }
+
+    let next = AtomicU64::new(2);
+    let stop = AtomicBool::new(false);
+    std::thread::scope(|s| {
+        let (tx, rx) = mpsc::channel();
+        for w in 0..jobs {
+            let (tx, run, next, stop) = (tx.clone(), &run, &next, &stop);
+            s.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let t = next.fetch_add(1, Ordering::Relaxed);
+                    if t > amount as u64 {
+                        break;
+                    }
+                    let res = run(w, t as u32);
+                    // anything after a missing test is missing too (or doesn't matter)
+                    let last = !matches!(res, Ok(Some(_)));
+                    if tx.send((t as u32, res)).is_err() || last {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        // results can come in any order, so they wait here until it's their turn
+        let mut done = BTreeMap::new();
+        let mut want = 2;
+        let res = (|| {
+            for (t, res) in rx {
+                done.insert(t, res);
+                while let Some(res) = done.remove(&want) {
+                    let res = res?;
+                    let exists = res.is_some();
+                    if !report(want, res)? || !exists || want == amount {
+                        return Ok(());
+                    }
+                    want += 1;
+                }
+            }
+            Ok(())
+        })();
+        stop.store(true, Ordering::Relaxed);
+        res
+    })
+}
@@ -0,0 +1,145 @@
+use std::ffi::CStr;
+use std::io::Error;
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+
+/// how many processes a sandboxed program can have going at once
+/// (threads count too, and the JVM likes having a lot of them)
+const MAX_PROCS: libc::rlim_t = 256;
+
+/// syscalls that get the program killed on the spot
+const DENIED: &[libc::c_long] = &[
+    libc::SYS_ptrace,
+    libc::SYS_process_vm_readv,
+    libc::SYS_process_vm_writev,
+    libc::SYS_mount,
+    libc::SYS_umount2,
+    libc::SYS_pivot_root,
+    libc::SYS_chroot,
+    libc::SYS_unshare,
+    libc::SYS_setns,
+    libc::SYS_reboot,
+    libc::SYS_kexec_load,
+    libc::SYS_init_module,
+    libc::SYS_finit_module,
+    libc::SYS_delete_module,
+    libc::SYS_swapon,
+    libc::SYS_swapoff,
+    libc::SYS_bpf,
+    libc::SYS_perf_event_open,
+    libc::SYS_keyctl,
+    libc::SYS_add_key,
+    libc::SYS_request_key,
+];
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc00000b7;
+
+/// makes a command run in the sandbox, for code you don't trust- it gets its own
+/// network namespace (so no network at all), a cap on how many processes it can have
+/// & a syscall filter that kills it if it tries anything funny (which shows up as SIGSYS)
+pub(crate) fn sandbox(cmd: &mut Command) {
+    // everything has to be ready before forking, since the child can't allocate
+    // SAFETY: getuid & getgid always work
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    let uid_map = format!("{} {} 1", uid, uid).into_bytes();
+    let gid_map = format!("{} {} 1", gid, gid).into_bytes();
+    let filter = filter();
+
+    // SAFETY: everything in here is a plain syscall, which is async-signal-safe
+    unsafe {
+        cmd.pre_exec(move || {
+            // a new user namespace is what lets us make the others without being root
+            check(libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET))?;
+            write_file(c"/proc/self/setgroups", b"deny")?;
+            write_file(c"/proc/self/uid_map", &uid_map)?;
+            write_file(c"/proc/self/gid_map", &gid_map)?;
+
+            let lim = libc::rlimit { rlim_cur: MAX_PROCS, rlim_max: MAX_PROCS };
+            check(libc::setrlimit(libc::RLIMIT_NPROC, &lim))?;
+
+            let prog = libc::sock_fprog {
+                len: filter.len() as u16,
+                filter: filter.as_ptr() as *mut libc::sock_filter
+            };
+            check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+            check(libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &prog))?;
+            Ok(())
+        });
+    }
+}
+
+/// makes sure the sandbox can actually be set up here by running aargh itself in it,
+/// since some systems don't let normal users make namespaces
+/// (e.g. ubuntu's default apparmor rules, or docker's default seccomp profile)
+pub(crate) fn probe() -> std::io::Result<()> {
+    let mut cmd = Command::new(std::env::current_exe()?);
+    cmd.arg("--version").stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::null());
+    sandbox(&mut cmd);
+    let status = cmd.status()?;
+    if !status.success() {
+        return Err(Error::other(format!("a test run in it failed ({})", status)));
+    }
+    Ok(())
+}
+
+fn check(ret: libc::c_int) -> std::io::Result<()> {
+    if ret < 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// writes to a file without allocating anything
+fn write_file(path: &CStr, data: &[u8]) -> std::io::Result<()> {
+    // SAFETY: the path's null-terminated & the buffer's as long as we say it is
+    unsafe {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY);
+        check(fd)?;
+        let written = libc::write(fd, data.as_ptr() as *const libc::c_void, data.len());
+        libc::close(fd);
+        if written < 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// the seccomp filter (a little BPF program that looks at each syscall)
+fn filter() -> Vec<libc::sock_filter> {
+    let stmt = |code: u32, k: u32| libc::sock_filter { code: code as u16, jt: 0, jf: 0, k };
+    let jump = |code: u32, k: u32, jt: u8, jf: u8| libc::sock_filter {
+        code: (libc::BPF_JMP | code | libc::BPF_K) as u16, jt, jf, k
+    };
+    let load = |offset: u32| stmt(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, offset);
+    let kill = stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS);
+    let allow = stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW);
+
+    // offsets into struct seccomp_data
+    let (nr, arch, arg0) = (0, 4, 16);
+    let mut res = vec![
+        // syscall numbers mean different things on other architectures
+        load(arch),
+        jump(libc::BPF_JEQ, AUDIT_ARCH, 1, 0),
+        kill,
+        load(nr),
+    ];
+    // x32 syscalls have the same arch but their own numbers
+    if cfg!(target_arch = "x86_64") {
+        res.extend([jump(libc::BPF_JGE, 0x40000000, 0, 1), kill]);
+    }
+    for &sys in DENIED {
+        res.extend([jump(libc::BPF_JEQ, sys as u32, 0, 1), kill]);
+    }
+    // sockets are fine as long as they're local
+    res.extend([
+        jump(libc::BPF_JEQ, libc::SYS_socket as u32, 0, 3),
+        load(arg0),
+        jump(libc::BPF_JEQ, libc::AF_UNIX as u32, 1, 0),
+        kill,
+        allow,
+    ]);
+    res
+}
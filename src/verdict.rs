@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result};
+
+use crate::cli::Prog;
+use crate::errors::ExecError;
+
+/// how a single test case went
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Verdict {
+    Accepted,
+    WrongAnswer,
+    PresentationError,
+    TimeLimit,
+    MemoryLimit,
+    RuntimeError,
+    CompileError,
+    OutputLimit,
+    SecurityViolation
+}
+
+impl Verdict {
+    /// the verdict for an error that the program itself is responsible for
+    /// (`None` if it's aaargh's fault or the user's setup is messed up)
+    pub(crate) fn from_error(err: &ExecError) -> Option<Verdict> {
+        match err {
+            ExecError::TimeLimit(_) => Some(Verdict::TimeLimit),
+            ExecError::MemoryLimit(_) => Some(Verdict::MemoryLimit),
+            ExecError::OutputLimit(_) => Some(Verdict::OutputLimit),
+            ExecError::RuntimeError(_) => Some(Verdict::RuntimeError),
+            // anything else not compiling is on the user
+            ExecError::CompileError(ce) if ce.prog == Prog::Code => Some(Verdict::CompileError),
+            ExecError::Security(_) => Some(Verdict::SecurityViolation),
+            _ => None
+        }
+    }
+
+    pub(crate) fn abbr(&self) -> &str {
+        match self {
+            Verdict::Accepted => "AC",
+            Verdict::WrongAnswer => "WA",
+            Verdict::PresentationError => "PE",
+            Verdict::TimeLimit => "TLE",
+            Verdict::MemoryLimit => "MLE",
+            Verdict::RuntimeError => "RE",
+            Verdict::CompileError => "CE",
+            Verdict::OutputLimit => "OLE",
+            Verdict::SecurityViolation => "SV"
+        }
+    }
+
+    pub(crate) fn desc(&self) -> &str {
+        match self {
+            Verdict::Accepted => "accepted",
+            Verdict::WrongAnswer => "wrong answer",
+            Verdict::PresentationError => "presentation error",
+            Verdict::TimeLimit => "time limit exceeded",
+            Verdict::MemoryLimit => "memory limit exceeded",
+            Verdict::RuntimeError => "runtime error",
+            Verdict::CompileError => "compilation error",
+            Verdict::OutputLimit => "output limit exceeded",
+            Verdict::SecurityViolation => "security violation"
+        }
+    }
+
+    pub(crate) fn is_ok(&self) -> bool {
+        *self == Verdict::Accepted
+    }
+}
+
+impl Display for Verdict {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{} ({})", self.abbr(), self.desc())
+    }
+}
+
+/// tally of the verdicts over a whole run
+#[derive(Debug, Default)]
+pub(crate) struct Summary { counts: BTreeMap<Verdict, u32> }
+
+impl Summary {
+    pub(crate) fn add(&mut self, verdict: Verdict) {
+        *self.counts.entry(verdict).or_insert(0) += 1;
+    }
+
+    pub(crate) fn all_ok(&self) -> bool {
+        self.counts.keys().all(Verdict::is_ok)
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        let counts: Vec<String> = self.counts.iter()
+            .map(|(v, c)| format!("{} {}", c, v.abbr()))
+            .collect();
+        write!(f, "{}", counts.join(", "))
+    }
+}